@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The user sent SIGINT or SIGTERM.
+    Signal,
+    /// Too many cells failed, see `--fail-fast` and `--max-failures`.
+    Failures,
+}
+
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+static BY_FAILURES: AtomicBool = AtomicBool::new(false);
+static GROUPS: Mutex<Option<HashSet<i32>>> = Mutex::new(None);
+
+fn signal_groups(signal: libc::c_int) {
+    if let Some(groups) = GROUPS.lock().unwrap().as_ref() {
+        for pgid in groups {
+            unsafe {
+                libc::kill(-pgid, signal);
+            }
+        }
+    }
+}
+
+/// Installs the SIGINT / SIGTERM handler.
+///
+/// The first signal stops scheduling new work and asks every running tool to
+/// terminate; the second one kills them outright.
+pub fn install() {
+    ctrlc::set_handler(|| {
+        let count = SIGNALS.fetch_add(1, Ordering::SeqCst) + 1;
+        signal_groups(if count == 1 {
+            libc::SIGTERM
+        } else {
+            libc::SIGKILL
+        });
+    })
+    .expect("failed to install signal handler");
+}
+
+/// Cancels the run from within, as if the user had pressed Ctrl-C once.
+pub fn request(reason: Reason) {
+    if SIGNALS
+        .compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        BY_FAILURES.store(reason == Reason::Failures, Ordering::SeqCst);
+        signal_groups(libc::SIGTERM);
+    }
+}
+
+/// True once the run was cancelled.
+pub fn requested() -> bool {
+    return SIGNALS.load(Ordering::SeqCst) > 0;
+}
+
+pub fn reason() -> Option<Reason> {
+    if !requested() {
+        return None;
+    } else if BY_FAILURES.load(Ordering::SeqCst) {
+        return Some(Reason::Failures);
+    }
+    return Some(Reason::Signal);
+}
+
+/// Tracks a process group so it is signalled on cancellation. A group that
+/// starts after cancellation was requested is terminated right away.
+pub fn register(pgid: i32) {
+    GROUPS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashSet::new)
+        .insert(pgid);
+
+    if requested() {
+        unsafe {
+            libc::kill(-pgid, libc::SIGTERM);
+        }
+    }
+}
+
+pub fn unregister(pgid: i32) {
+    if let Some(groups) = GROUPS.lock().unwrap().as_mut() {
+        groups.remove(&pgid);
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Reads `NAME:TYPE=VALUE` entries from a CMakeCache.txt.
+pub fn read_cache(build_dir: &Path) -> Option<HashMap<String, String>> {
+    let content = fs::read_to_string(build_dir.join("CMakeCache.txt")).ok()?;
+    let mut cache = HashMap::new();
+    for line in content.lines() {
+        if line.starts_with("//") || line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let name = key.split(':').next().unwrap();
+            cache.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    return Some(cache);
+}
+
+fn same_path(a: &str, b: &str) -> bool {
+    return match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    };
+}
+
+/// True if the build directory was configured with `arguments` and its
+/// generated build files are newer than the cache.
+pub fn is_up_to_date(build_dir: &Path, arguments: &[String]) -> bool {
+    let cache = match read_cache(build_dir) {
+        Some(cache) => cache,
+        None => return false,
+    };
+
+    for argument in arguments {
+        let matches = if let Some(definition) = argument.strip_prefix("-D") {
+            match definition.split_once('=') {
+                Some((key, value)) => {
+                    let name = key.split(':').next().unwrap();
+                    cache.get(name).map(String::as_str) == Some(value)
+                }
+                None => false,
+            }
+        } else if let Some(generator) = argument.strip_prefix("-G") {
+            cache.get("CMAKE_GENERATOR").map(String::as_str) == Some(generator)
+        } else if argument.starts_with('-') {
+            true
+        } else {
+            cache
+                .get("CMAKE_HOME_DIRECTORY")
+                .is_some_and(|home| same_path(home, argument))
+        };
+
+        if !matches {
+            return false;
+        }
+    }
+
+    let modified = |name: &str| fs::metadata(build_dir.join(name)).and_then(|m| m.modified());
+    return match (modified("build.ninja"), modified("CMakeCache.txt")) {
+        (Ok(generated), Ok(cache)) => generated >= cache,
+        _ => false,
+    };
+}
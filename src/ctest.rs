@@ -0,0 +1,175 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::process::Command;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Timeout,
+    NotRun,
+}
+
+impl fmt::Display for TestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TestStatus::Passed => "passed",
+            TestStatus::Failed => "failed",
+            TestStatus::Timeout => "timeout",
+            TestStatus::NotRun => "not run",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A single test reported by ctest.
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    pub name: String,
+    pub status: TestStatus,
+    pub duration: Duration,
+    /// Status text as printed by ctest, e.g. `Exception: SegFault`.
+    pub detail: String,
+}
+
+/// A parsed `n/m Test #k: name .... Passed 0.12 sec` line.
+pub struct Progress {
+    pub current: u64,
+    pub total: u64,
+    pub test: TestCase,
+}
+
+/// Extracts per-test results from ctest output lines.
+pub struct Parser {
+    re: Regex,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        let re = Regex::new(
+            r"^\s*(?P<current>\d+)/(?P<total>\d+)\s+Test\s+#\d+:\s+(?P<name>\S+)\s+\.*\s*(?:\*\*\*)?(?P<detail>.*?)\s+(?P<seconds>\d+(?:\.\d+)?)\s+sec\s*$",
+        )
+        .unwrap();
+        return Self { re };
+    }
+
+    pub fn parse(&self, line: &str) -> Option<Progress> {
+        let caps = self.re.captures(line)?;
+        let detail = caps["detail"].trim().to_string();
+        let status = if detail.starts_with("Passed") {
+            TestStatus::Passed
+        } else if detail.starts_with("Timeout") {
+            TestStatus::Timeout
+        } else if detail.starts_with("Not Run")
+            || detail.starts_with("Disabled")
+            || detail.starts_with("Skipped")
+        {
+            TestStatus::NotRun
+        } else {
+            TestStatus::Failed
+        };
+
+        return Some(Progress {
+            current: caps["current"].parse().ok()?,
+            total: caps["total"].parse().ok()?,
+            test: TestCase {
+                name: caps["name"].to_string(),
+                status,
+                duration: Duration::from_secs_f64(caps["seconds"].parse().ok()?),
+                detail,
+            },
+        });
+    }
+}
+
+/// Name of the report `ctest --output-junit` writes into a cell's build directory.
+pub const JUNIT_FILE: &str = "ctest-junit.xml";
+
+/// Returns true if the `ctest` on `PATH` supports `--output-junit` (CMake 3.21+).
+pub fn supports_junit() -> bool {
+    let output = match Command::new("ctest").arg("--version").output() {
+        Ok(output) => output,
+        Err(_) => return false,
+    };
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let version = text
+        .lines()
+        .next()
+        .and_then(|line| line.rsplit(' ').next())
+        .unwrap_or("");
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+
+    return (major, minor) >= (3, 21);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Progress {
+        return Parser::new().parse(line).expect("a test result");
+    }
+
+    #[test]
+    fn passed() {
+        let progress =
+            parse("1/3 Test #1: cub.cpp14.test.foo ...............   Passed    0.10 sec");
+        assert_eq!((progress.current, progress.total), (1, 3));
+        assert_eq!(progress.test.name, "cub.cpp14.test.foo");
+        assert_eq!(progress.test.status, TestStatus::Passed);
+        assert_eq!(progress.test.detail, "Passed");
+        assert_eq!(progress.test.duration, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn failed() {
+        let progress =
+            parse("2/3 Test #2: cub.cpp14.test.bar ...............***Failed    0.34 sec");
+        assert_eq!(progress.test.status, TestStatus::Failed);
+        assert_eq!(progress.test.detail, "Failed");
+
+        let progress = parse(
+            "3/3 Test #3: cub.cpp14.test.baz ...............***Exception: SegFault  1.02 sec",
+        );
+        assert_eq!(progress.test.status, TestStatus::Failed);
+        assert_eq!(progress.test.detail, "Exception: SegFault");
+    }
+
+    #[test]
+    fn timeout() {
+        let progress = parse(" 7/12 Test  #7: cub.cpp17.test.scan .........***Timeout 600.02 sec");
+        assert_eq!((progress.current, progress.total), (7, 12));
+        assert_eq!(progress.test.status, TestStatus::Timeout);
+    }
+
+    #[test]
+    fn not_run() {
+        let progress = parse(
+            "4/5 Test #4: cub.cpp11.test.sort ..............***Not Run (Disabled)   0.00 sec",
+        );
+        assert_eq!(progress.test.status, TestStatus::NotRun);
+        assert_eq!(progress.test.detail, "Not Run (Disabled)");
+
+        let progress =
+            parse("5/5 Test #5: cub.cpp11.test.scan ..............***Skipped   0.01 sec");
+        assert_eq!(progress.test.status, TestStatus::NotRun);
+    }
+
+    #[test]
+    fn other_lines() {
+        let parser = Parser::new();
+        assert!(parser.parse("    Start 1: cub.cpp14.test.foo").is_none());
+        assert!(parser
+            .parse("Test project /build/11.6/debug/gcc/9/14")
+            .is_none());
+        assert!(parser
+            .parse("67% tests passed, 1 tests failed out of 3")
+            .is_none());
+    }
+}
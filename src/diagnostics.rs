@@ -0,0 +1,178 @@
+use regex::Regex;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.file, self.line, severity, self.message
+        )
+    }
+}
+
+/// Extracts compiler and CMake diagnostics from tool output lines.
+pub struct Parser {
+    patterns: Vec<Regex>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        let patterns = [
+            // gcc / clang: file:line:col: error: message
+            r"^(?P<file>[^\s:][^:]*):(?P<line>\d+):(?:\d+:)?\s*(?:fatal )?(?P<severity>error|warning):\s*(?P<message>.*)$",
+            // nvcc / cudafe: file(line): error: message
+            r"^(?P<file>[^\s(][^(]*)\((?P<line>\d+)\):\s*(?P<severity>error|warning)[^:]*:\s*(?P<message>.*)$",
+            // nvc++: "file", line N: error: message
+            r#"^"(?P<file>[^"]+)", line (?P<line>\d+):\s*(?P<severity>error|warning)[^:]*:\s*(?P<message>.*)$"#,
+            // CMake Error at file:line (message):
+            r"^CMake (?P<severity>Error|Warning)(?: \(dev\))? at (?P<file>[^:]+):(?P<line>\d+)\s*(?P<message>.*)$",
+        ];
+
+        return Self {
+            patterns: patterns.iter().map(|p| Regex::new(p).unwrap()).collect(),
+        };
+    }
+
+    pub fn parse(&self, line: &str) -> Option<Diagnostic> {
+        for re in &self.patterns {
+            if let Some(caps) = re.captures(line) {
+                let severity = if caps["severity"].eq_ignore_ascii_case("error") {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                };
+
+                return Some(Diagnostic {
+                    severity,
+                    file: caps["file"].to_string(),
+                    line: caps["line"].parse().unwrap_or(0),
+                    message: caps["message"].trim().to_string(),
+                });
+            }
+        }
+
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> (Severity, String, u32, String) {
+        let diagnostic = Parser::new().parse(line).expect("a diagnostic");
+        return (
+            diagnostic.severity,
+            diagnostic.file,
+            diagnostic.line,
+            diagnostic.message,
+        );
+    }
+
+    #[test]
+    fn gcc() {
+        assert_eq!(
+            parse("/src/cub/test/foo.cu:12:5: error: 'x' was not declared in this scope"),
+            (
+                Severity::Error,
+                "/src/cub/test/foo.cu".to_string(),
+                12,
+                "'x' was not declared in this scope".to_string()
+            )
+        );
+        assert_eq!(
+            parse("/src/cub/cub/util.cuh:7: warning: unused variable 'y'"),
+            (
+                Severity::Warning,
+                "/src/cub/cub/util.cuh".to_string(),
+                7,
+                "unused variable 'y'".to_string()
+            )
+        );
+        assert_eq!(
+            parse("foo.cu:3:1: fatal error: bar.h: No such file or directory").0,
+            Severity::Error
+        );
+    }
+
+    #[test]
+    fn nvcc() {
+        assert_eq!(
+            parse("/src/cub/test/foo.cu(12): error: identifier \"x\" is undefined"),
+            (
+                Severity::Error,
+                "/src/cub/test/foo.cu".to_string(),
+                12,
+                "identifier \"x\" is undefined".to_string()
+            )
+        );
+        assert_eq!(
+            parse("/src/cub/cub/util.cuh(40): warning #177-D: variable \"y\" was declared but never referenced"),
+            (
+                Severity::Warning,
+                "/src/cub/cub/util.cuh".to_string(),
+                40,
+                "variable \"y\" was declared but never referenced".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn nvcxx() {
+        assert_eq!(
+            parse(r#""/src/cub/test/foo.cu", line 9: error: expected a ";""#),
+            (
+                Severity::Error,
+                "/src/cub/test/foo.cu".to_string(),
+                9,
+                "expected a \";\"".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn cmake() {
+        assert_eq!(
+            parse("CMake Error at CMakeLists.txt:10 (find_package):"),
+            (
+                Severity::Error,
+                "CMakeLists.txt".to_string(),
+                10,
+                "(find_package):".to_string()
+            )
+        );
+        assert_eq!(
+            parse("CMake Warning (dev) at cmake/Thrust.cmake:3 (message):").0,
+            Severity::Warning
+        );
+    }
+
+    #[test]
+    fn other_lines() {
+        let parser = Parser::new();
+        assert!(parser.parse("[3/10] Building CUDA object foo.o").is_none());
+        assert!(parser.parse("-- Configuring done").is_none());
+        assert!(parser.parse("error: no file").is_none());
+    }
+}
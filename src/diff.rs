@@ -0,0 +1,193 @@
+use crate::ctest::TestStatus;
+use crate::history::{CellRecord, Run};
+use crate::process::format_duration;
+use crate::report::cell_label;
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+// Build time changes below either bound are noise.
+const MIN_BUILD_SECONDS: f64 = 30.0;
+const MIN_BUILD_CHANGE: f64 = 0.2;
+
+/// Differences between two runs, each entry a printable line.
+#[derive(Default)]
+pub struct Diff {
+    pub regressed: Vec<String>,
+    pub fixed: Vec<String>,
+    pub appeared: Vec<String>,
+    pub disappeared: Vec<String>,
+    pub build_time: Vec<String>,
+    pub warnings: Vec<String>,
+    pub toolchains: Vec<String>,
+}
+
+fn test_statuses(cell: &CellRecord) -> BTreeMap<&str, TestStatus> {
+    let mut statuses: BTreeMap<&str, TestStatus> = cell
+        .passed
+        .iter()
+        .map(|name| (name.as_str(), TestStatus::Passed))
+        .collect();
+    for test in &cell.tests {
+        statuses.insert(&test.name, test.status);
+    }
+    return statuses;
+}
+
+// Exact version of every toolchain of a run, or the banners when comparing
+// with a run recorded before toolchains were probed.
+fn toolchain_versions(run: &Run, exact: bool) -> BTreeMap<&str, &str> {
+    if !exact {
+        return run
+            .compilers
+            .iter()
+            .chain(&run.ctks)
+            .map(|(label, banner)| (label.as_str(), banner.as_str()))
+            .collect();
+    }
+    return run
+        .toolchains
+        .iter()
+        .map(|(label, probe)| (label.as_str(), probe.exact()))
+        .collect();
+}
+
+impl Diff {
+    pub fn new(before: &Run, after: &Run) -> Self {
+        let mut diff = Diff::default();
+
+        let exact = !before.toolchains.is_empty() && !after.toolchains.is_empty();
+        let versions_before = toolchain_versions(before, exact);
+        for (label, b) in toolchain_versions(after, exact) {
+            if let Some(a) = versions_before.get(label) {
+                if *a != b {
+                    diff.toolchains.push(format!("{} {} -> {}", label, a, b));
+                }
+            }
+        }
+
+        let cells_before: BTreeMap<String, &CellRecord> = before
+            .cells
+            .iter()
+            .map(|cell| (cell_label(&cell.key()), cell))
+            .collect();
+        let cells_after: BTreeMap<String, &CellRecord> = after
+            .cells
+            .iter()
+            .map(|cell| (cell_label(&cell.key()), cell))
+            .collect();
+
+        for (label, cell) in &cells_before {
+            if !cells_after.contains_key(label) {
+                diff.disappeared
+                    .push(format!("{} {}", label, cell.outcome.symbol()));
+            }
+        }
+
+        for (label, b) in &cells_after {
+            let a = match cells_before.get(label) {
+                Some(a) => a,
+                None => {
+                    diff.appeared
+                        .push(format!("{} {}", label, b.outcome.symbol()));
+                    continue;
+                }
+            };
+
+            let change = format!("{} {} -> {}", label, a.outcome.symbol(), b.outcome.symbol());
+            if a.outcome.passed() && b.outcome.failed() {
+                diff.regressed.push(change);
+            } else if a.outcome.failed() && b.outcome.passed() {
+                diff.fixed.push(change);
+            }
+
+            diff.compare_tests(label, a, b);
+
+            let (ta, tb) = (a.build_seconds, b.build_seconds);
+            if ta.max(tb) >= MIN_BUILD_SECONDS
+                && ta > 0.0
+                && tb > 0.0
+                && (tb - ta).abs() / ta >= MIN_BUILD_CHANGE
+            {
+                diff.build_time.push(format!(
+                    "{} {} -> {} ({:+.0}%)",
+                    label,
+                    format_duration(Duration::from_secs_f64(ta)),
+                    format_duration(Duration::from_secs_f64(tb)),
+                    (tb - ta) / ta * 100.0
+                ));
+            }
+
+            if a.warnings != b.warnings {
+                diff.warnings
+                    .push(format!("{} {} -> {}", label, a.warnings, b.warnings));
+            }
+        }
+
+        return diff;
+    }
+
+    // Tests can only be compared when ctest ran in both cells, otherwise every
+    // test of a broken build would show up as disappeared.
+    fn compare_tests(&mut self, label: &str, a: &CellRecord, b: &CellRecord) {
+        if !a.tested() || !b.tested() {
+            return;
+        }
+
+        let before = test_statuses(a);
+        let after = test_statuses(b);
+        let names: BTreeSet<&str> = before.keys().chain(after.keys()).copied().collect();
+        for name in names {
+            match (before.get(name), after.get(name)) {
+                (Some(sa), Some(sb)) => {
+                    let change = format!("{} test {} {} -> {}", label, name, sa, sb);
+                    let test_failed =
+                        |s: &TestStatus| matches!(s, TestStatus::Failed | TestStatus::Timeout);
+                    if *sa == TestStatus::Passed && test_failed(sb) {
+                        self.regressed.push(change);
+                    } else if test_failed(sa) && *sb == TestStatus::Passed {
+                        self.fixed.push(change);
+                    }
+                }
+                (None, Some(sb)) => self
+                    .appeared
+                    .push(format!("{} test {} {}", label, name, sb)),
+                (Some(sa), None) => self
+                    .disappeared
+                    .push(format!("{} test {} {}", label, name, sa)),
+                (None, None) => {}
+            }
+        }
+    }
+
+    pub fn print(&self) {
+        let sections = [
+            ("Regressed", &self.regressed, Color::Red),
+            ("Fixed", &self.fixed, Color::Green),
+            ("New", &self.appeared, Color::Cyan),
+            ("Disappeared", &self.disappeared, Color::Yellow),
+            ("Build time", &self.build_time, Color::Magenta),
+            ("Warnings", &self.warnings, Color::Magenta),
+            ("Toolchains", &self.toolchains, Color::Blue),
+        ];
+
+        let mut empty = true;
+        for (title, lines, color) in sections {
+            if lines.is_empty() {
+                continue;
+            }
+            empty = false;
+            println!(
+                "{}",
+                format!("{} ({})", title, lines.len()).color(color).bold()
+            );
+            for line in lines {
+                println!("  {}", line);
+            }
+        }
+
+        if empty {
+            println!("No differences");
+        }
+    }
+}
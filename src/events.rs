@@ -0,0 +1,154 @@
+//! Machine-readable stream of the events of a run, written with `--events`.
+//! With `--events -` the stream goes to stdout in place of the summary.
+//!
+//! Every line is a JSON object with an `event` field naming its kind and an
+//! `elapsed` field holding the seconds since the run started. Cells are named
+//! by their label, `<type>/<ctk>/<compiler>/cpp.<dialect>`, and phases are one
+//! of `configure`, `build` or `test`. The other fields by kind:
+//!
+//! - `run-started`: `schema` (version of this format, currently 1), `action`
+//!   (`config`, `build`, `test` or `clean`), `command` (array of arguments),
+//!   `timestamp` (Unix seconds) and `cells` (number of selected cells).
+//! - `cell-queued`: `cell`, `build_type`, `ctk`, `compiler` and `cpp`, sent for
+//!   every selected cell, including cached ones.
+//! - `cell-started`: `cell`, sent when its first phase starts.
+//! - `phase-started`: `cell` and `phase`.
+//! - `phase-finished`: `cell`, `phase`, `succeeded` (bool) and `seconds`.
+//! - `progress`: `cell`, `phase`, `current` and `total` build edges or tests.
+//! - `diagnostic`: `cell`, `severity` (`error` or `warning`), `file`, `line`
+//!   and `message`.
+//! - `test-result`: `cell`, `name`, `status` (`passed`, `failed`, `timeout` or
+//!   `not-run`), `detail` (ctest's status text) and `seconds`.
+//! - `cell-finished`: `cell`, `outcome` (`passed`, `failed`, `timed-out`,
+//!   `interrupted`, `not-run` or `cached`) and `seconds`.
+//! - `run-finished`: `run` (id in the history, or null if it was not
+//!   recorded), `seconds` and `outcomes` (number of cells per outcome).
+//!
+//! New kinds and fields may be added without changing `schema`, consumers
+//! should ignore what they do not know.
+
+use crate::ctest::TestStatus;
+use crate::diagnostics::Severity;
+use crate::Outcome;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub const SCHEMA: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    RunStarted {
+        schema: u32,
+        action: &'a str,
+        command: &'a [String],
+        timestamp: i64,
+        cells: usize,
+    },
+    CellQueued {
+        cell: &'a str,
+        build_type: &'a str,
+        ctk: &'a str,
+        compiler: &'a str,
+        cpp: &'a str,
+    },
+    CellStarted {
+        cell: &'a str,
+    },
+    PhaseStarted {
+        cell: &'a str,
+        phase: &'a str,
+    },
+    PhaseFinished {
+        cell: &'a str,
+        phase: &'a str,
+        succeeded: bool,
+        seconds: f64,
+    },
+    Progress {
+        cell: &'a str,
+        phase: &'a str,
+        current: u64,
+        total: u64,
+    },
+    Diagnostic {
+        cell: &'a str,
+        severity: Severity,
+        file: &'a str,
+        line: u32,
+        message: &'a str,
+    },
+    TestResult {
+        cell: &'a str,
+        name: &'a str,
+        status: TestStatus,
+        detail: &'a str,
+        seconds: f64,
+    },
+    CellFinished {
+        cell: &'a str,
+        outcome: Outcome,
+        seconds: f64,
+    },
+    RunFinished {
+        run: Option<u64>,
+        seconds: f64,
+        outcomes: BTreeMap<&'a str, usize>,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+    elapsed: f64,
+}
+
+/// Destination of the events, discarding them unless opened with a path.
+pub struct Sink {
+    out: Option<Mutex<Box<dyn Write + Send>>>,
+    started: Instant,
+}
+
+impl Sink {
+    pub fn disabled() -> Self {
+        return Self {
+            out: None,
+            started: Instant::now(),
+        };
+    }
+
+    /// Writes the events to the file at `path`, or to stdout for `-`.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(fs::File::create(path)?)
+        };
+        return Ok(Self {
+            out: Some(Mutex::new(out)),
+            started: Instant::now(),
+        });
+    }
+
+    // Each event is flushed right away so that readers can follow the run.
+    pub fn emit(&self, event: Event) {
+        let out = match &self.out {
+            Some(out) => out,
+            None => return,
+        };
+        let line = Line {
+            event: &event,
+            elapsed: self.started.elapsed().as_secs_f64(),
+        };
+        let mut out = out.lock().unwrap();
+        if let Ok(json) = serde_json::to_string(&line) {
+            writeln!(out, "{}", json).ok();
+            out.flush().ok();
+        }
+    }
+}
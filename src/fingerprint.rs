@@ -0,0 +1,46 @@
+use crate::git;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Accumulates everything a cell's result depends on into one hash.
+pub struct Fingerprint {
+    hasher: Sha256,
+}
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        return Self {
+            hasher: Sha256::new(),
+        };
+    }
+
+    pub fn add(&mut self, part: &[u8]) -> &mut Self {
+        // Length-prefixed so that adjacent parts cannot run into each other
+        self.hasher.update((part.len() as u64).to_le_bytes());
+        self.hasher.update(part);
+        return self;
+    }
+
+    pub fn finish(&self) -> String {
+        let digest = self.hasher.clone().finalize();
+        return digest.iter().map(|b| format!("{:02x}", b)).collect();
+    }
+}
+
+/// State of every configured source tree, sorted by name. Fails if any of
+/// them is not a git checkout, as its changes could not be detected.
+pub fn sources(src: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+    let mut names: Vec<&String> = src.keys().collect();
+    names.sort();
+
+    let mut state = Vec::new();
+    for name in names {
+        state.extend_from_slice(name.as_bytes());
+        state.push(0);
+        let tree =
+            git::working_tree(&src[name]).ok_or(format!("{} is not a git checkout", src[name]))?;
+        state.extend(tree);
+        state.push(0);
+    }
+    return Ok(state);
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Commit checked out in the repository at `path`.
+pub fn head(path: &str) -> Option<String> {
+    let output = git(path, &["rev-parse", "HEAD"])?;
+    return Some(String::from_utf8_lossy(&output).trim().to_string());
+}
+
+fn git(path: &str, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    return Some(output.stdout);
+}
+
+/// Describes the working tree at `path`: the tree hash of `HEAD` followed by
+/// uncommitted changes and the contents of untracked files, so that any edit
+/// changes the description.
+pub fn working_tree(path: &str) -> Option<Vec<u8>> {
+    let mut state = git(path, &["rev-parse", "HEAD^{tree}"])?;
+    state.extend(git(path, &["diff", "HEAD", "--binary"])?);
+
+    let untracked = git(path, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+    for file in untracked.split(|b| *b == 0).filter(|f| !f.is_empty()) {
+        state.extend_from_slice(file);
+        state.push(0);
+        let name = String::from_utf8_lossy(file);
+        state.extend(fs::read(Path::new(path).join(name.as_ref())).unwrap_or_default());
+    }
+
+    return Some(state);
+}
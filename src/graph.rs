@@ -0,0 +1,64 @@
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Matches ninja status lines such as `[12/345] Building CUDA object ...`.
+pub fn status_regex() -> Regex {
+    return Regex::new(r"^\[(?P<current>\d+)/(?P<total>\d+)\]").unwrap();
+}
+
+/// Path of a cell's build directory relative to the build root, used as its
+/// `CMAKE_NINJA_OUTPUT_PATH_PREFIX` so that the cell's `build.ninja` can be
+/// included from the build root via `subninja`.
+pub fn output_prefix(build_root: &Path, build_dir: &Path) -> String {
+    let relative = build_dir.strip_prefix(build_root).unwrap_or(build_dir);
+    return format!("{}/", relative.display());
+}
+
+/// Writes the top-level `build.ninja` pulling in every listed cell.
+pub fn write_build_file(build_root: &Path, prefixes: &[&str]) -> io::Result<()> {
+    let mut content = String::new();
+    content.push_str("# Generated by cccl-composer, do not edit.\n");
+    content.push_str("ninja_required_version = 1.5\n\n");
+    for prefix in prefixes {
+        content.push_str(&format!("subninja {}build.ninja\n", prefix));
+    }
+
+    return fs::write(build_root.join("build.ninja"), content);
+}
+
+/// Returns the index of the cell whose output prefix appears in `line`.
+pub fn owner(line: &str, prefixes: &[&str]) -> Option<usize> {
+    return prefixes
+        .iter()
+        .enumerate()
+        .filter(|(_, prefix)| line.contains(*prefix))
+        .max_by_key(|(_, prefix)| prefix.len())
+        .map(|(i, _)| i);
+}
+
+/// Counts the edges each cell still has to build with a ninja dry run.
+pub fn count_edges(build_root: &Path, targets: &[String], prefixes: &[&str]) -> Vec<u64> {
+    let mut counts = vec![0; prefixes.len()];
+
+    let output = Command::new("ninja")
+        .arg(format!("-C{}", build_root.display()))
+        .args(["-n", "-k0"])
+        .args(targets)
+        .output();
+
+    if let Ok(output) = output {
+        let re = status_regex();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if re.is_match(line) {
+                if let Some(i) = owner(line, prefixes) {
+                    counts[i] += 1;
+                }
+            }
+        }
+    }
+
+    return counts;
+}
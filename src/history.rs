@@ -0,0 +1,107 @@
+use crate::ctest::TestStatus;
+use crate::toolchain::Probe;
+use crate::{CellKey, Outcome};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+const FILE: &str = "runs.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestRecord {
+    pub name: String,
+    pub status: TestStatus,
+    #[serde(default)]
+    pub detail: String,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellRecord {
+    pub build_type: String,
+    pub ctk: String,
+    pub compiler: String,
+    pub cpp: String,
+    pub outcome: Outcome,
+    pub seconds: f64,
+    /// Time spent in the build phase.
+    #[serde(default)]
+    pub build_seconds: f64,
+    #[serde(default)]
+    pub warnings: usize,
+    /// Identifies the cell's inputs, present for builds and tests.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    /// Names of the tests that passed.
+    #[serde(default)]
+    pub passed: Vec<String>,
+    /// Tests that did not pass.
+    #[serde(default)]
+    pub tests: Vec<TestRecord>,
+}
+
+impl CellRecord {
+    pub fn key(&self) -> CellKey<'_> {
+        return (&self.build_type, &self.ctk, &self.cpp, &self.compiler);
+    }
+
+    /// True if ctest ran in this cell.
+    pub fn tested(&self) -> bool {
+        return !self.passed.is_empty() || !self.tests.is_empty();
+    }
+}
+
+/// One invocation of a matrix subcommand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Run {
+    pub id: u64,
+    /// Start of the run in seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub action: String,
+    pub command: Vec<String>,
+    /// Targets given with `--targets`, empty if everything was built.
+    #[serde(default)]
+    pub targets: Vec<String>,
+    pub seconds: f64,
+    /// Commit of every configured source tree.
+    #[serde(default)]
+    pub sources: BTreeMap<String, String>,
+    /// Version banners of the selected compilers and CTKs.
+    #[serde(default)]
+    pub compilers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub ctks: BTreeMap<String, String>,
+    /// Path, modification time and exact version of the selected compilers
+    /// and CTKs by label.
+    #[serde(default)]
+    pub toolchains: BTreeMap<String, Probe>,
+    pub cells: Vec<CellRecord>,
+}
+
+/// Every recorded run, oldest first. Lines that fail to parse are skipped.
+pub fn load(state_dir: &Path) -> Vec<Run> {
+    let content = fs::read_to_string(state_dir.join(FILE)).unwrap_or_default();
+    return content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+}
+
+pub fn find(state_dir: &Path, id: u64) -> Option<Run> {
+    return load(state_dir).into_iter().find(|run| run.id == id);
+}
+
+/// Appends `run` to the history, assigning it the next free id.
+pub fn append(state_dir: &Path, run: &mut Run) -> io::Result<()> {
+    run.id = load(state_dir).last().map_or(1, |last| last.id + 1);
+
+    fs::create_dir_all(state_dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(state_dir.join(FILE))?;
+    return writeln!(file, "{}", serde_json::to_string(run)?);
+}
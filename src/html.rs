@@ -0,0 +1,361 @@
+use crate::ctest::TestStatus;
+use crate::diagnostics::Severity;
+use crate::history::Run;
+use crate::junit::{escape, log_excerpt};
+use crate::process::format_duration;
+use crate::report::cell_label;
+use crate::{cell_dir, format_timestamp, BuildResult, CellKey, Phase};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+// Lines of the failed phase's log shown for each failed cell.
+const LOG_EXCERPT_LINES: usize = 100;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1, h2 { font-weight: normal; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; }
+th { background: #f4f4f4; }
+td.num { text-align: right; }
+td.passed { background: #7c7; }
+td.cached { background: #bdb; }
+td.failed { background: #e66; }
+td.timed-out { background: #b7e; }
+td.interrupted { background: #fb5; }
+td.not-run { background: #ddd; }
+td.test-passed { background: #7c7; }
+td.test-failed { background: #e66; }
+td.test-timeout { background: #b7e; }
+td.test-not-run { background: #ddd; }
+td a { color: inherit; }
+pre { background: #f8f8f8; padding: 0.5em; overflow-x: auto; }
+summary { cursor: pointer; }
+.error { color: #c00; }
+.warning { color: #a60; }
+";
+
+// Outside a minute the report shows the same durations as the terminal.
+fn seconds(duration: Duration) -> String {
+    if duration.as_secs() < 60 {
+        return format!("{:.1}s", duration.as_secs_f64());
+    }
+    return format_duration(duration);
+}
+
+fn test_class(status: TestStatus) -> &'static str {
+    return match status {
+        TestStatus::Passed => "test-passed",
+        TestStatus::Failed => "test-failed",
+        TestStatus::Timeout => "test-timeout",
+        TestStatus::NotRun => "test-not-run",
+    };
+}
+
+fn header(html: &mut String, run: &Run, result: &BuildResult, cells: &[CellKey]) {
+    html.push_str(&format!("<h1>cccl-composer run {}</h1>\n", run.id));
+    html.push_str(&format!(
+        "<p><code>{}</code><br>started {}, took {}</p>\n",
+        escape(&run.command.join(" ")),
+        format_timestamp(run.timestamp),
+        format_duration(Duration::from_secs_f64(run.seconds))
+    ));
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (build_type, ctk, cpp, compiler) in cells {
+        *counts
+            .entry(result.outcome(build_type, ctk, cpp, compiler).name())
+            .or_default() += 1;
+    }
+    html.push_str("<p>");
+    for (name, count) in counts {
+        html.push_str(&format!("{} {} &nbsp; ", count, name));
+    }
+    html.push_str("</p>\n");
+
+    html.push_str("<table>\n");
+    for (name, sha) in &run.sources {
+        html.push_str(&format!(
+            "<tr><th>{}</th><td>{}</td></tr>\n",
+            escape(name),
+            escape(sha)
+        ));
+    }
+    for (label, probe) in &run.toolchains {
+        html.push_str(&format!(
+            "<tr><th>{}</th><td>{} <span title=\"{}\">{}</span></td></tr>\n",
+            escape(label),
+            escape(probe.exact()),
+            escape(&probe.banner),
+            escape(&probe.path)
+        ));
+    }
+    html.push_str("</table>\n");
+}
+
+// Outcomes colored by cell, a row per build type, CTK and compiler and a
+// column per dialect, linking to the cell's details.
+fn matrix(html: &mut String, result: &BuildResult, cells: &[CellKey]) {
+    let mut cpps: Vec<&str> = Vec::new();
+    let mut rows: Vec<(&str, &str, &str)> = Vec::new();
+    for (build_type, ctk, cpp, compiler) in cells {
+        if !cpps.contains(cpp) {
+            cpps.push(cpp);
+        }
+        if !rows.contains(&(build_type, ctk, compiler)) {
+            rows.push((build_type, ctk, compiler));
+        }
+    }
+
+    html.push_str("<h2>Matrix</h2>\n<table>\n<tr><th>type</th><th>CTK</th><th>compiler</th>");
+    for cpp in &cpps {
+        html.push_str(&format!("<th>C++{}</th>", escape(cpp)));
+    }
+    html.push_str("</tr>\n");
+    for (build_type, ctk, compiler) in &rows {
+        html.push_str(&format!(
+            "<tr><th>{}</th><th>{}</th><th>{}</th>",
+            escape(build_type),
+            escape(ctk),
+            escape(compiler)
+        ));
+        for cpp in &cpps {
+            match cells
+                .iter()
+                .position(|c| *c == (*build_type, *ctk, *cpp, *compiler))
+            {
+                Some(i) => {
+                    let name = result.outcome(build_type, ctk, cpp, compiler).name();
+                    html.push_str(&format!(
+                        "<td class=\"{}\"><a href=\"#cell-{}\">{}</a></td>",
+                        name, i, name
+                    ));
+                }
+                None => html.push_str("<td></td>"),
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+// Phase timings of every cell, followed by the errors, failed tests and log
+// excerpt of each cell that did not pass.
+fn cell_details(html: &mut String, result: &BuildResult, cells: &[CellKey], build_root: &Path) {
+    let phases = [Phase::Configure, Phase::Build, Phase::Test];
+
+    html.push_str("<h2>Cells</h2>\n<table>\n<tr><th>cell</th><th>outcome</th>");
+    for phase in phases {
+        html.push_str(&format!("<th>{}</th>", phase.name()));
+    }
+    html.push_str("<th>total</th><th>errors</th><th>warnings</th><th>tests</th></tr>\n");
+    for (i, cell) in cells.iter().enumerate() {
+        let (build_type, ctk, cpp, compiler) = *cell;
+        let name = result.outcome(build_type, ctk, cpp, compiler).name();
+        html.push_str(&format!(
+            "<tr id=\"cell-{}\"><td>{}</td><td class=\"{}\">{}</td>",
+            i,
+            escape(&cell_label(cell)),
+            name,
+            name
+        ));
+        match result.reports.get(cell) {
+            Some(report) => {
+                for phase in phases {
+                    html.push_str(&format!(
+                        "<td class=\"num\">{}</td>",
+                        seconds(report.phase_duration(phase))
+                    ));
+                }
+                let count = |severity: Severity| {
+                    report
+                        .diagnostics
+                        .iter()
+                        .filter(|d| d.severity == severity)
+                        .count()
+                };
+                let passed = report
+                    .tests
+                    .iter()
+                    .filter(|t| t.status == TestStatus::Passed)
+                    .count();
+                html.push_str(&format!(
+                    "<td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}/{}</td>",
+                    seconds(report.duration),
+                    count(Severity::Error),
+                    count(Severity::Warning),
+                    passed,
+                    report.tests.len()
+                ));
+            }
+            None => html.push_str(&"<td></td>".repeat(7)),
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+
+    for cell in cells {
+        let (build_type, ctk, cpp, compiler) = *cell;
+        let outcome = result.outcome(build_type, ctk, cpp, compiler);
+        let report = match result.reports.get(cell) {
+            Some(report) if outcome.failed() => report,
+            _ => continue,
+        };
+
+        html.push_str(&format!(
+            "<details>\n<summary class=\"error\">{} {}</summary>\n",
+            escape(&cell_label(cell)),
+            outcome.name()
+        ));
+        if let Some(timeout) = &report.timeout {
+            html.push_str(&format!(
+                "<p class=\"error\">{} {}</p>\n",
+                timeout.phase.name(),
+                escape(&timeout.expiry.to_string())
+            ));
+        }
+        let errors: Vec<String> = report
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.to_string())
+            .collect();
+        if !errors.is_empty() {
+            html.push_str(&format!("<pre>{}</pre>\n", escape(&errors.join("\n"))));
+        }
+        for test in &report.tests {
+            if matches!(test.status, TestStatus::Failed | TestStatus::Timeout) {
+                html.push_str(&format!(
+                    "<p>test {} <span class=\"error\">{}</span> ({:.2}s)</p>\n",
+                    escape(&test.name),
+                    escape(&test.detail),
+                    test.duration.as_secs_f64()
+                ));
+            }
+        }
+        if let Some(phase) = report.failed_phase {
+            let log = cell_dir(build_root, build_type, ctk, cpp, compiler)
+                .join(format!("{}.log", phase.name()));
+            html.push_str(&format!(
+                "<details><summary>last lines of {}</summary>\n<pre>{}</pre>\n</details>\n",
+                escape(&log.display().to_string()),
+                escape(&log_excerpt(&log, LOG_EXCERPT_LINES))
+            ));
+        }
+        html.push_str("</details>\n");
+    }
+}
+
+// Identical diagnostics of all cells grouped together, most frequent first.
+fn diagnostics(html: &mut String, result: &BuildResult, cells: &[CellKey]) {
+    let mut groups: BTreeMap<(bool, String), BTreeSet<String>> = BTreeMap::new();
+    for cell in cells {
+        if let Some(report) = result.reports.get(cell) {
+            for diagnostic in &report.diagnostics {
+                groups
+                    .entry((
+                        diagnostic.severity == Severity::Warning,
+                        diagnostic.to_string(),
+                    ))
+                    .or_default()
+                    .insert(cell_label(cell));
+            }
+        }
+    }
+    if groups.is_empty() {
+        return;
+    }
+
+    let mut groups: Vec<((bool, String), BTreeSet<String>)> = groups.into_iter().collect();
+    groups.sort_by(|(a, a_cells), (b, b_cells)| {
+        return a.0.cmp(&b.0).then(b_cells.len().cmp(&a_cells.len()));
+    });
+
+    html.push_str("<h2>Diagnostics</h2>\n<table>\n<tr><th>diagnostic</th><th>cells</th></tr>\n");
+    for ((warning, text), labels) in groups {
+        html.push_str(&format!(
+            "<tr><td class=\"{}\"><code>{}</code></td><td><details><summary>{}</summary>{}</details></td></tr>\n",
+            if warning { "warning" } else { "error" },
+            escape(&text),
+            labels.len(),
+            labels.iter().map(|l| escape(l)).collect::<Vec<_>>().join("<br>")
+        ));
+    }
+    html.push_str("</table>\n");
+}
+
+// A row per test and a column per cell that ran tests.
+fn tests(html: &mut String, result: &BuildResult, cells: &[CellKey]) {
+    let mut names: BTreeSet<&str> = BTreeSet::new();
+    let mut columns: Vec<(usize, BTreeMap<&str, TestStatus>)> = Vec::new();
+    for (i, cell) in cells.iter().enumerate() {
+        let report = match result.reports.get(cell) {
+            Some(report) if !report.tests.is_empty() => report,
+            _ => continue,
+        };
+        let mut statuses = BTreeMap::new();
+        for test in &report.tests {
+            names.insert(&test.name);
+            statuses.insert(test.name.as_str(), test.status);
+        }
+        columns.push((i, statuses));
+    }
+    if names.is_empty() {
+        return;
+    }
+
+    html.push_str("<h2>Tests</h2>\n<table>\n<tr><th>test</th>");
+    for (i, _) in &columns {
+        html.push_str(&format!(
+            "<th title=\"{}\"><a href=\"#cell-{}\">{}</a></th>",
+            escape(&cell_label(&cells[*i])),
+            i,
+            i
+        ));
+    }
+    html.push_str("</tr>\n");
+    for name in names {
+        html.push_str(&format!("<tr><td>{}</td>", escape(name)));
+        for (_, statuses) in &columns {
+            match statuses.get(name) {
+                Some(status) => html.push_str(&format!(
+                    "<td class=\"{}\">{}</td>",
+                    test_class(*status),
+                    status
+                )),
+                None => html.push_str("<td></td>"),
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+/// Writes a self-contained HTML report of a run, without external assets.
+pub fn write(
+    path: &Path,
+    run: &Run,
+    result: &BuildResult,
+    cells: &[CellKey],
+    build_root: &Path,
+) -> io::Result<()> {
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!(
+        "<title>cccl-composer run {}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        run.id, STYLE
+    ));
+    header(&mut html, run, result, cells);
+    matrix(&mut html, result, cells);
+    cell_details(&mut html, result, cells, build_root);
+    diagnostics(&mut html, result, cells);
+    tests(&mut html, result, cells);
+    html.push_str("</body>\n</html>\n");
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    return fs::write(path, html);
+}
@@ -0,0 +1,334 @@
+use crate::{toolchain, AppConfig, DefaultsConfig, Exit, BUILD_TYPES, DIALECTS};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+// Same layout as the configuration file read by `AppConfig`, with sorted maps
+// so that the written file is stable.
+#[derive(Serialize)]
+struct NewConfig {
+    src: BTreeMap<String, String>,
+    compilers: BTreeMap<String, String>,
+    ctks: BTreeMap<String, String>,
+    defaults: DefaultsConfig,
+}
+
+pub fn command<'a>() -> Command<'a> {
+    return Command::new("init")
+        .about("Create the configuration file from the toolchains found on this machine.")
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .action(ArgAction::SetTrue)
+                .help("accept the detected sources, every toolchain found and all axes without asking."),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("overwrite an existing configuration."),
+        )
+        .arg(
+            Arg::new("src")
+                .long("src")
+                .value_name("PATH")
+                .action(ArgAction::Set)
+                .help("cccl monorepo or cub checkout, found from the working directory by default."),
+        );
+}
+
+fn is_monorepo(root: &Path) -> bool {
+    return root.join("cub").join("cub").join("cub.cuh").is_file()
+        && root
+            .join("thrust")
+            .join("thrust")
+            .join("version.h")
+            .is_file();
+}
+
+fn is_cub(root: &Path) -> bool {
+    return root.join("cub").join("cub.cuh").is_file();
+}
+
+fn is_thrust(root: &Path) -> bool {
+    return root.join("thrust").join("version.h").is_file();
+}
+
+// The cub and thrust sources of a checkout. A cub checkout only resolves
+// thrust when it is cloned next to it.
+fn sources(root: &Path) -> Option<(PathBuf, Option<PathBuf>)> {
+    if is_monorepo(root) {
+        return Some((root.join("cub"), Some(root.join("thrust"))));
+    }
+    if is_cub(root) {
+        let thrust = root.parent().map(|parent| parent.join("thrust"));
+        return Some((root.to_path_buf(), thrust.filter(|t| is_thrust(t))));
+    }
+    return None;
+}
+
+// Closest checkout containing the working directory.
+fn detect_root() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    return cwd
+        .ancestors()
+        .find(|dir| sources(dir).is_some())
+        .map(Path::to_path_buf);
+}
+
+fn absolute(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    return fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+}
+
+struct Prompt {
+    interactive: bool,
+    input: io::StdinLock<'static>,
+}
+
+impl Prompt {
+    // Reads a line, returning `default` when it is empty, on end of input or
+    // without asking at all in non-interactive mode.
+    fn ask(&mut self, question: &str, default: &str) -> String {
+        if !self.interactive {
+            return default.to_string();
+        }
+        print!("{} [{}]: ", question, default);
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            return default.to_string();
+        }
+        let answer = line.trim();
+        if answer.is_empty() {
+            return default.to_string();
+        }
+        return answer.to_string();
+    }
+
+    // Comma separated subset of `choices`, `all` picks every one of them.
+    fn pick(&mut self, question: &str, choices: &[&str], default: &[&str]) -> Vec<String> {
+        let default = if default == choices {
+            "all".to_string()
+        } else {
+            default.join(",")
+        };
+        loop {
+            let answer = self.ask(question, &default);
+            if answer == "all" {
+                return choices.iter().map(|c| c.to_string()).collect();
+            }
+            let picked: Vec<String> = answer
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+            match picked.iter().find(|p| !choices.contains(&p.as_str())) {
+                Some(unknown) => println!(
+                    "{}",
+                    format!("Unknown {}, pick from {}", unknown, choices.join(", ")).red()
+                ),
+                None => return picked,
+            }
+        }
+    }
+}
+
+// Only records a default when it narrows the axis, so that toolchains added
+// to the file later are selected as well.
+fn narrowed(picked: Vec<String>, all: &[&str]) -> Option<Vec<String>> {
+    if picked.len() == all.len() {
+        return None;
+    }
+    return Some(picked);
+}
+
+fn resolve_sources(
+    prompt: &mut Prompt,
+    matches: &ArgMatches,
+) -> Result<(PathBuf, PathBuf), String> {
+    let detected = match matches.get_one::<String>("src") {
+        Some(src) => Some(absolute(src)),
+        None => detect_root(),
+    };
+
+    let root = match detected {
+        Some(root) => prompt.ask("cccl monorepo or cub checkout", &root.display().to_string()),
+        None if prompt.interactive => prompt.ask("cccl monorepo or cub checkout", ""),
+        None => String::new(),
+    };
+    if root.is_empty() {
+        return Err("No cccl or cub checkout found, pass one with --src".to_string());
+    }
+
+    let root = absolute(&root);
+    let (cub, thrust) = match sources(&root) {
+        Some(sources) => sources,
+        None => {
+            return Err(format!(
+                "{} is neither a cccl monorepo nor a cub checkout",
+                root.display()
+            ))
+        }
+    };
+
+    let thrust = match thrust {
+        Some(thrust) => thrust,
+        None if prompt.interactive => absolute(&prompt.ask("thrust checkout", "")),
+        None => PathBuf::new(),
+    };
+    if !is_thrust(&thrust) {
+        return Err(format!(
+            "No thrust checkout next to {}, clone it there or use the monorepo",
+            cub.display()
+        ));
+    }
+
+    return Ok((cub, thrust));
+}
+
+// Subset of the discovered toolchains to write, printing what was found.
+fn pick_toolchains(
+    prompt: &mut Prompt,
+    kind: &str,
+    found: BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    if found.is_empty() {
+        println!("{}", format!("No {} found", kind).yellow());
+        return found;
+    }
+
+    println!("Found {}:", kind);
+    for (label, path) in &found {
+        println!("  {:<14} {}", label, path);
+    }
+    let labels: Vec<&str> = found.keys().map(String::as_str).collect();
+    let picked = prompt.pick(&format!("Use {}", kind), &labels, &labels);
+    return found
+        .into_iter()
+        .filter(|(label, _)| picked.contains(label))
+        .collect();
+}
+
+// Asks for toolkits until one is given when none was discovered, CMake cannot
+// configure any cell without one.
+fn ask_ctks(prompt: &mut Prompt) -> BTreeMap<String, String> {
+    let mut ctks = BTreeMap::new();
+    while prompt.interactive {
+        let path = prompt.ask("CUDA toolkit path", "");
+        if path.is_empty() {
+            break;
+        }
+        let path = absolute(&path).display().to_string();
+        match toolchain::ctk_label(&path) {
+            Some(label) => {
+                println!("  {:<14} {}", label, path);
+                ctks.insert(label, path);
+                break;
+            }
+            None => println!(
+                "{}",
+                format!("No nvcc found in {}", toolchain::nvcc(&path).display()).red()
+            ),
+        }
+    }
+    return ctks;
+}
+
+fn write(path: &Path, config: &NewConfig) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(config).map_err(io::Error::from)?;
+    return fs::write(path, json + "\n");
+}
+
+pub fn run(matches: &ArgMatches) -> Exit {
+    let path = AppConfig::path();
+    if path.exists() && !matches.get_flag("force") {
+        println!(
+            "{}",
+            format!(
+                "Configuration {} already exists, pass --force to overwrite it",
+                path.display()
+            )
+            .red()
+        );
+        return Exit::Config;
+    }
+
+    let mut prompt = Prompt {
+        interactive: !matches.get_flag("yes"),
+        input: io::stdin().lock(),
+    };
+
+    let (cub, thrust) = match resolve_sources(&mut prompt, matches) {
+        Ok(sources) => sources,
+        Err(message) => {
+            println!("{}", message.red());
+            return Exit::Config;
+        }
+    };
+
+    let compilers = pick_toolchains(&mut prompt, "compilers", toolchain::discover_compilers());
+    let mut ctks = pick_toolchains(&mut prompt, "CUDA toolkits", toolchain::discover_ctks());
+    if ctks.is_empty() {
+        ctks = ask_ctks(&mut prompt);
+    }
+    if ctks.is_empty() {
+        println!(
+            "{}",
+            "No CUDA toolkit found, install one or point CUDA_HOME at it".red()
+        );
+        return Exit::Config;
+    }
+
+    let compiler_labels: Vec<&str> = compilers.keys().map(String::as_str).collect();
+    let ctk_labels: Vec<&str> = ctks.keys().map(String::as_str).collect();
+    let types = prompt.pick("Default build types", &BUILD_TYPES, &BUILD_TYPES);
+    let dialects = prompt.pick("Default C++ dialects", &DIALECTS, &DIALECTS);
+    let default_compilers = match compilers.is_empty() {
+        true => Vec::new(),
+        false => prompt.pick("Default compilers", &compiler_labels, &compiler_labels),
+    };
+    let default_ctks = match ctks.is_empty() {
+        true => Vec::new(),
+        false => prompt.pick("Default CUDA toolkits", &ctk_labels, &ctk_labels),
+    };
+
+    let defaults = DefaultsConfig {
+        types: narrowed(types, &BUILD_TYPES),
+        dialects: narrowed(dialects, &DIALECTS),
+        compilers: narrowed(default_compilers, &compiler_labels),
+        ctks: narrowed(default_ctks, &ctk_labels),
+    };
+
+    let config = NewConfig {
+        src: BTreeMap::from([
+            ("cub".to_string(), cub.display().to_string()),
+            ("thrust".to_string(), thrust.display().to_string()),
+        ]),
+        compilers,
+        ctks,
+        defaults,
+    };
+
+    if let Err(err) = write(&path, &config) {
+        println!(
+            "{}",
+            format!("Failed to write {}: {}", path.display(), err).red()
+        );
+        return Exit::Config;
+    }
+    println!("Wrote {}", path.display());
+    return Exit::Passed;
+}
@@ -0,0 +1,133 @@
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A pool of job tokens shared by every concurrent build.
+///
+/// Tokens live in a named pipe and follow the GNU make jobserver protocol
+/// (`--jobserver-auth=fifo:PATH`), which make 4.4+ and ninja 1.13+ honor.
+/// The composer takes one token per running cell to cover the implicit job
+/// slot of its ninja process; ninja acquires any further tokens itself, so
+/// capacity released by finished cells flows to the ones still building.
+pub struct JobServer {
+    path: PathBuf,
+    fifo: File,
+    nonblocking: File,
+    jobs: usize,
+}
+
+/// A job token held by the composer, returned to the pool on drop.
+pub struct Token<'a> {
+    server: &'a JobServer,
+}
+
+impl JobServer {
+    pub fn new(dir: &Path, jobs: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(".jobserver-{}", std::process::id()));
+        fs::remove_file(&path).ok();
+
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Opening both ends keeps the pipe alive regardless of which clients
+        // are attached and makes the open itself non-blocking.
+        let mut fifo = OpenOptions::new().read(true).write(true).open(&path)?;
+        fifo.write_all(&vec![b'+'; jobs])?;
+
+        let nonblocking = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)?;
+
+        return Ok(Self {
+            path,
+            fifo,
+            nonblocking,
+            jobs,
+        });
+    }
+
+    pub fn jobs(&self) -> usize {
+        return self.jobs;
+    }
+
+    /// Blocks until a token is available.
+    pub fn acquire(&self) -> io::Result<Token<'_>> {
+        let mut token = [0u8; 1];
+        (&self.fifo).read_exact(&mut token)?;
+        return Ok(Token { server: self });
+    }
+
+    /// Number of tokens currently sitting in the pool.
+    pub fn available(&self) -> usize {
+        let mut n: libc::c_int = 0;
+        if unsafe { libc::ioctl(self.fifo.as_raw_fd(), libc::FIONREAD, &mut n) } != 0 {
+            return 0;
+        }
+        return n.max(0) as usize;
+    }
+
+    /// Takes a token out of circulation without blocking. Returns false if the
+    /// pool is empty. The token must be handed back with `restore`.
+    pub fn withhold(&self) -> bool {
+        let mut token = [0u8; 1];
+        return matches!((&self.nonblocking).read(&mut token), Ok(1));
+    }
+
+    /// Returns a token taken with `withhold` to the pool.
+    pub fn restore(&self) {
+        (&self.fifo).write_all(b"+").ok();
+    }
+
+    /// Lets `cmd` draw tokens from this pool.
+    pub fn configure(&self, cmd: &mut Command) {
+        cmd.env(
+            "MAKEFLAGS",
+            format!(
+                "-j{} --jobserver-auth=fifo:{}",
+                self.jobs,
+                self.path.display()
+            ),
+        );
+    }
+}
+
+impl Drop for Token<'_> {
+    fn drop(&mut self) {
+        self.server.restore();
+    }
+}
+
+impl Drop for JobServer {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Returns true if the `ninja` on `PATH` acts as a jobserver client (1.13+).
+pub fn ninja_supports_jobserver() -> bool {
+    let output = match Command::new("ninja").arg("--version").output() {
+        Ok(output) => output,
+        Err(_) => return false,
+    };
+
+    let version = String::from_utf8_lossy(&output.stdout);
+    let mut parts = version
+        .trim()
+        .split('.')
+        .map(|p| p.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+
+    return (major, minor) >= (1, 13);
+}
@@ -0,0 +1,168 @@
+use regex::{NoExpand, Regex};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not valid XML
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+/// How a test case ended.
+pub enum Verdict<'a> {
+    Passed,
+    Failed { message: &'a str, output: &'a str },
+    Skipped { message: &'a str },
+}
+
+/// The test suite of one matrix cell.
+pub struct Suite {
+    name: String,
+    body: String,
+    tests: usize,
+    failures: usize,
+    skipped: usize,
+    time: f64,
+}
+
+impl Suite {
+    pub fn new(name: String) -> Self {
+        return Self {
+            name,
+            body: String::new(),
+            tests: 0,
+            failures: 0,
+            skipped: 0,
+            time: 0.0,
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.tests == 0;
+    }
+
+    pub fn add_case(&mut self, name: &str, duration: Duration, verdict: Verdict) {
+        let time = duration.as_secs_f64();
+        self.tests += 1;
+        self.time += time;
+
+        self.body.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            escape(name),
+            escape(&self.name),
+            time
+        ));
+        match verdict {
+            Verdict::Passed => self.body.push_str("/>\n"),
+            Verdict::Failed { message, output } => {
+                self.failures += 1;
+                self.body.push_str(&format!(
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                    escape(message),
+                    escape(output)
+                ));
+            }
+            Verdict::Skipped { message } => {
+                self.skipped += 1;
+                self.body.push_str(&format!(
+                    ">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                    escape(message)
+                ));
+            }
+        }
+    }
+
+    /// Moves the test cases of a report written by `ctest --output-junit`
+    /// into this suite. Returns false if the file is missing or malformed.
+    pub fn merge_ctest(&mut self, path: &Path) -> bool {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return false,
+        };
+
+        let start = match content
+            .find("<testsuite")
+            .and_then(|open| content[open..].find('>').map(|end| open + end + 1))
+        {
+            Some(start) => start,
+            None => return false,
+        };
+        let end = match content.rfind("</testsuite>") {
+            Some(end) if end >= start => end,
+            _ => return false,
+        };
+        let cases = &content[start..end];
+
+        self.tests += cases.matches("<testcase").count();
+        self.failures += cases.matches("<failure").count();
+        self.skipped += cases.matches("<skipped").count();
+        for time in cases.split("<testcase").skip(1).filter_map(|case| {
+            let attr = case.split_once("time=\"")?.1;
+            return attr.split_once('"')?.0.parse::<f64>().ok();
+        }) {
+            self.time += time;
+        }
+
+        // ctest names the class after the test, use the cell like our own cases
+        let classname = Regex::new(r#"classname="[^"]*""#).unwrap();
+        let replacement = format!("classname=\"{}\"", escape(&self.name));
+        for line in cases.lines().filter(|l| !l.trim().is_empty()) {
+            self.body.push_str("  ");
+            self.body
+                .push_str(&classname.replace(line, NoExpand(&replacement)));
+            self.body.push('\n');
+        }
+        return true;
+    }
+}
+
+pub fn write(path: &Path, suites: &[Suite]) -> io::Result<()> {
+    let tests: usize = suites.iter().map(|s| s.tests).sum();
+    let failures: usize = suites.iter().map(|s| s.failures).sum();
+    let skipped: usize = suites.iter().map(|s| s.skipped).sum();
+    let time: f64 = suites.iter().map(|s| s.time).sum();
+
+    let mut content = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    content.push_str(&format!(
+        "<testsuites name=\"cccl-composer\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        tests, failures, skipped, time
+    ));
+    for suite in suites {
+        content.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            escape(&suite.name),
+            suite.tests,
+            suite.failures,
+            suite.skipped,
+            suite.time
+        ));
+        content.push_str(&suite.body);
+        content.push_str("  </testsuite>\n");
+    }
+    content.push_str("</testsuites>\n");
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    return fs::write(path, content);
+}
+
+/// Last `n` lines of a log file, used as the output of synthetic failures.
+pub fn log_excerpt(path: &Path, n: usize) -> String {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let lines: Vec<&str> = content.lines().collect();
+    return lines[lines.len().saturating_sub(n)..].join("\n");
+}
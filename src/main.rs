@@ -1,22 +1,64 @@
-use clap::{Arg, ArgAction, ArgMatches, Command};
+#![allow(clippy::needless_return)]
+
+mod cancel;
+mod cmake;
+mod ctest;
+mod diagnostics;
+mod diff;
+mod events;
+mod fingerprint;
+mod git;
+mod graph;
+mod history;
+mod html;
+mod init;
+mod jobserver;
+mod junit;
+mod manifest;
+mod memory;
+mod process;
+mod progress;
+mod report;
+mod toolchain;
+
+use chrono::{Local, TimeZone};
+use clap::{Arg, ArgAction, ArgMatches, Command, PossibleValue};
 use clap_complete::{generate, shells::Zsh};
 use colored::*;
 use config::{Config, ConfigError, File};
+use ctest::{TestCase, TestStatus};
+use diagnostics::{Diagnostic, Severity};
 use dirs::config_dir;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::ProgressBar;
+use jobserver::JobServer;
+use junit::Verdict;
+use memory::Governor;
 use prettytable::{Row, Table};
-use rayon;
-use regex::Regex;
-use serde::Deserialize;
-use std::collections::HashMap;
+use process::{Expiry, Finished};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::env;
 use std::fs;
 use std::io;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
-use std::process::Command as ProcCommand;
-use std::process::Stdio;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcCommand, ExitCode};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const BUILD_TYPES: [&str; 2] = ["debug", "release"];
+const DIALECTS: [&str; 3] = ["11", "14", "17"];
+
+// Log lines kept for cells whose phase timed out
+const TIMEOUT_TAIL_LINES: usize = 10;
+
+// Log lines attached to synthetic JUnit failures of configure and build
+const JUNIT_LOG_LINES: usize = 50;
+
+// Log lines shown in the GitHub Actions log group of a failed cell
+const GROUP_LOG_LINES: usize = 50;
 
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
@@ -38,6 +80,65 @@ struct AppConfig {
     src: HashMap<String, String>,
     compilers: HashMap<String, String>,
     ctks: HashMap<String, String>,
+    #[serde(default)]
+    memory: MemoryConfig,
+    #[serde(default)]
+    timeouts: TimeoutConfig,
+    #[serde(default)]
+    defaults: DefaultsConfig,
+}
+
+// Axes selected when the command line does not name them, all of them if unset.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct DefaultsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    types: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dialects: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compilers: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ctks: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TimeoutConfig {
+    configure: Option<String>,
+    build: Option<String>,
+    test: Option<String>,
+    per_test: Option<String>,
+    stall: Option<String>,
+}
+
+// Phase timeouts resolved from the command line and config file.
+#[derive(Debug, Default)]
+struct Timeouts {
+    configure: Option<Duration>,
+    build: Option<Duration>,
+    test: Option<Duration>,
+    per_test: Option<Duration>,
+    stall: Option<Duration>,
+}
+
+impl Timeouts {
+    fn limits(&self, phase: Phase) -> process::Limits {
+        let timeout = match phase {
+            Phase::Configure => self.configure,
+            Phase::Build => self.build,
+            Phase::Test => self.test,
+        };
+
+        return process::Limits {
+            timeout,
+            stall: self.stall,
+        };
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MemoryConfig {
+    per_job: Option<String>,
+    budget: Option<String>,
 }
 
 impl AppConfig {
@@ -51,24 +152,109 @@ impl AppConfig {
 }
 
 impl AppConfig {
+    pub fn path() -> PathBuf {
+        return config_dir()
+            .unwrap()
+            .join("cccl-composer")
+            .join("config.json");
+    }
+
     pub fn new() -> Result<Self, ConfigError> {
-        let config_path = config_dir().unwrap();
-        let cccl_config_path = config_path.join("cccl-composer").join("config.json");
+        let cccl_config_path = AppConfig::path();
         let s = Config::builder()
             .add_source(File::with_name(cccl_config_path.to_str().unwrap()))
             .build()?;
 
-        s.try_deserialize()
+        let config: AppConfig = s.try_deserialize()?;
+        config.validate().map_err(ConfigError::Message)?;
+        return Ok(config);
+    }
+
+    // Values only parsed when they are used, checked upfront so that a typo
+    // is reported as a configuration error.
+    fn validate(&self) -> Result<(), String> {
+        let timeouts = &self.timeouts;
+        for (name, value) in [
+            ("configure", &timeouts.configure),
+            ("build", &timeouts.build),
+            ("test", &timeouts.test),
+            ("per_test", &timeouts.per_test),
+            ("stall", &timeouts.stall),
+        ] {
+            if let Some(value) = value {
+                process::parse_duration(value).map_err(|e| format!("timeouts.{}: {}", name, e))?;
+            }
+        }
+
+        for (name, value) in [
+            ("per_job", &self.memory.per_job),
+            ("budget", &self.memory.budget),
+        ] {
+            if let Some(value) = value {
+                memory::parse_size(value).map_err(|e| format!("memory.{}: {}", name, e))?;
+            }
+        }
+
+        let defaults = &self.defaults;
+        for (name, values, known) in [
+            ("types", &defaults.types, BUILD_TYPES.to_vec()),
+            ("dialects", &defaults.dialects, DIALECTS.to_vec()),
+            ("compilers", &defaults.compilers, self.compiler_labels()),
+            ("ctks", &defaults.ctks, self.ctk_labels()),
+        ] {
+            let mut known = known;
+            known.sort();
+            for value in values.iter().flatten() {
+                if !known.contains(&value.as_str()) {
+                    return Err(format!(
+                        "defaults.{}: unknown `{}`, expected one of {}",
+                        name,
+                        value,
+                        known.join(", ")
+                    ));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+// Exit status of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    Passed,
+    // Some cells failed or timed out, or a diff found regressions
+    Failed,
+    // Invalid arguments, the same status clap exits with
+    Usage,
+    Config,
+    Interrupted,
+}
+
+impl Exit {
+    fn code(&self) -> u8 {
+        return match self {
+            Exit::Passed => 0,
+            Exit::Failed => 1,
+            Exit::Usage => 2,
+            Exit::Config => 3,
+            Exit::Interrupted => 130,
+        };
     }
 }
 
-fn build_cli(config: &AppConfig) -> clap::App {
+fn build_cli(config: &AppConfig) -> clap::App<'_> {
     let compilers: Vec<&str> = config.compiler_labels();
     let ctks: Vec<&str> = config.ctk_labels();
 
     return Command::new("cccl-composer")
         .about("cccl infrastructure utility")
         .version("0.0.1")
+        .after_help(
+            "Exit status is 0 if all cells passed, 1 if some failed, 2 for invalid arguments, \
+             3 if the configuration cannot be loaded and 130 if the run was interrupted.",
+        )
         .subcommand_required(true)
         .arg_required_else_help(true)
         .author("CUDA C++ Core Libraries Team")
@@ -77,239 +263,658 @@ fn build_cli(config: &AppConfig) -> clap::App {
                 .short_flag('t')
                 .long_flag("test")
                 .about("Run CUB tests.")
+                .arg(single_graph_arg())
                 .arg(
-                    Arg::new("compilers")
-                        .short('c')
-                        .long("compilers")
+                    Arg::new("junit")
+                        .long("junit")
+                        .value_name("PATH")
                         .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(compilers.clone())
-                        .help("specify compilers."),
+                        .help("write a JUnit XML report with a test suite per cell."),
                 )
                 .arg(
-                    Arg::new("dialects")
-                        .short('d')
-                        .long("dialects")
+                    Arg::new("report")
+                        .long("report")
                         .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["11", "14", "17"])
-                        .help("specify C++ dialects."),
+                        .possible_values(["cells", "by-test"])
+                        .default_value("cells")
+                        .help("summarize outcomes per cell or per test."),
                 )
                 .arg(
-                    Arg::new("types")
-                        .short('t')
-                        .long("types")
+                    Arg::new("pivot")
+                        .long("pivot")
                         .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["debug", "release"])
-                        .help("specify build types."),
-                )
-                .arg(
-                    Arg::new("ctks")
-                        .long("ctks")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(ctks.clone())
-                        .help("specify CTK versions."),
+                        .possible_values(report::Axis::NAMES)
+                        .help("with --report by-test, merge cells into one column per value of this axis."),
                 )
+                .args(selector_args(&compilers, &ctks))
                 .arg(
                     Arg::new("targets")
                         .long("targets")
                         .help("targets")
                         .action(ArgAction::Set)
                         .multiple_values(true),
-                ),
+                )
+                .arg(format_arg())
+                .arg(html_arg())
+                .arg(progress_arg())
+                .arg(events_arg())
+                .args(layout_args())
+                .args(scheduling_args())
+                .args(rerun_args())
+                .arg(no_cache_arg())
+                .arg(reconfigure_arg())
+                .args(timeout_args()),
         )
         .subcommand(
             Command::new("config")
                 .short_flag('c')
                 .long_flag("configure")
                 .about("Configure CUB tests.")
-                .arg(
-                    Arg::new("compilers")
-                        .short('c')
-                        .long("compilers")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(compilers.clone())
-                        .help("specify compilers."),
-                )
-                .arg(
-                    Arg::new("dialects")
-                        .short('d')
-                        .long("dialects")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["11", "14", "17"])
-                        .help("specify C++ dialects."),
-                )
-                .arg(
-                    Arg::new("types")
-                        .short('t')
-                        .long("types")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["debug", "release"])
-                        .help("specify build types."),
-                )
-                .arg(
-                    Arg::new("ctks")
-                        .long("ctks")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(ctks.clone())
-                        .help("specify CTK versions."),
-                )
+                .args(selector_args(&compilers, &ctks))
+                .arg(format_arg())
+                .arg(html_arg())
+                .arg(progress_arg())
+                .arg(events_arg())
+                .args(layout_args())
+                .args(scheduling_args())
+                .arg(reconfigure_arg())
+                .args(timeout_args()),
         )
         .subcommand(
             Command::new("clean")
                 .long_flag("clean")
                 .about("Clean build directories.")
-                .arg(
-                    Arg::new("compilers")
-                        .short('c')
-                        .long("compilers")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(compilers.clone())
-                        .help("specify compilers."),
-                )
-                .arg(
-                    Arg::new("dialects")
-                        .short('d')
-                        .long("dialects")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["11", "14", "17"])
-                        .help("specify C++ dialects."),
-                )
-                .arg(
-                    Arg::new("types")
-                        .short('t')
-                        .long("types")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["debug", "release"])
-                        .help("specify build types."),
-                )
-                .arg(
-                    Arg::new("ctks")
-                        .long("ctks")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(ctks.clone())
-                        .help("specify CTK versions."),
-                )
+                .args(selector_args(&compilers, &ctks))
+                .arg(format_arg())
+                .arg(html_arg())
+                .arg(progress_arg())
+                .arg(events_arg())
+                .args(layout_args())
+                .args(scheduling_args()),
         )
         .subcommand(
             Command::new("build")
                 .short_flag('S')
                 .long_flag("build")
                 .about("Build CUB tests.")
+                .arg(single_graph_arg())
+                .args(selector_args(&compilers, &ctks))
                 .arg(
-                    Arg::new("compilers")
-                        .short('c')
-                        .long("compilers")
+                    Arg::new("targets")
+                        .long("targets")
+                        .help("targets")
                         .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(compilers)
-                        .help("specify compilers."),
+                        .multiple_values(true),
                 )
+                .arg(format_arg())
+                .arg(html_arg())
+                .arg(progress_arg())
+                .arg(events_arg())
+                .args(layout_args())
+                .args(scheduling_args())
+                .args(rerun_args())
+                .arg(no_cache_arg())
+                .arg(reconfigure_arg())
+                .args(timeout_args()),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show the last known state of the matrix.")
+                .args(selector_args(&compilers, &ctks))
+                .arg(format_arg())
+                .args(layout_args()),
+        )
+        .subcommand(init::command())
+        .subcommand(
+            Command::new("history")
+                .about("List past runs or show one of them.")
                 .arg(
-                    Arg::new("dialects")
-                        .short('d')
-                        .long("dialects")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["11", "14", "17"])
-                        .help("specify C++ dialects."),
+                    Arg::new("run")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("run to show."),
                 )
                 .arg(
-                    Arg::new("types")
-                        .short('t')
-                        .long("types")
+                    Arg::new("limit")
+                        .short('n')
+                        .long("limit")
                         .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(["debug", "release"])
-                        .help("specify build types."),
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20")
+                        .help("number of runs to list."),
                 )
+                .arg(format_arg())
+                .args(layout_args()),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two recorded runs, fail if the second one regressed.")
                 .arg(
-                    Arg::new("ctks")
-                        .long("ctks")
-                        .action(ArgAction::Set)
-                        .multiple_values(true)
-                        .possible_values(ctks)
-                        .help("specify CTK versions."),
+                    Arg::new("before")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u64))
+                        .help("run to compare against."),
                 )
                 .arg(
-                    Arg::new("targets")
-                        .long("targets")
-                        .help("targets")
-                        .action(ArgAction::Set)
-                        .multiple_values(true),
+                    Arg::new("after")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u64))
+                        .help("run to compare."),
                 ),
         )
         .subcommand(Command::new("generate-zsh-completions").about("Generate Zsh completions."));
 }
 
-fn get_compilers<'a>(config: &'a AppConfig, matches: &'a ArgMatches) -> Vec<&'a str> {
-    if matches.contains_id("compilers") {
-        return matches
-            .get_many::<String>("compilers")
-            .expect("contains_id")
-            .map(|s| s.as_str())
-            .collect();
-    } else {
-        return config.compiler_labels();
+// Arguments selecting the matrix cells a subcommand works on.
+fn selector_args<'a>(compilers: &[&'a str], ctks: &[&'a str]) -> Vec<Arg<'a>> {
+    return vec![
+        Arg::new("compilers")
+            .short('c')
+            .long("compilers")
+            .action(ArgAction::Set)
+            .multiple_values(true)
+            .possible_values(compilers.to_vec())
+            .help("specify compilers."),
+        Arg::new("dialects")
+            .short('d')
+            .long("dialects")
+            .action(ArgAction::Set)
+            .multiple_values(true)
+            .possible_values(DIALECTS)
+            .help("specify C++ dialects."),
+        Arg::new("types")
+            .short('t')
+            .long("types")
+            .action(ArgAction::Set)
+            .multiple_values(true)
+            .possible_values(BUILD_TYPES)
+            .help("specify build types."),
+        Arg::new("ctks")
+            .long("ctks")
+            .action(ArgAction::Set)
+            .multiple_values(true)
+            .possible_values(ctks.to_vec())
+            .help("specify CTK versions."),
+    ];
+}
+
+fn format_arg<'a>() -> Arg<'a> {
+    return Arg::new("format")
+        .long("format")
+        .action(ArgAction::Set)
+        .possible_values(report::Format::NAMES)
+        .default_value("table")
+        .help("print the summary as a table or as json, csv or markdown.");
+}
+
+// Arguments switching the summary from nested tables to a flat pivot table.
+fn layout_args<'a>() -> Vec<Arg<'a>> {
+    let axes = || {
+        report::Axis::NAMES.map(|name| match name {
+            "dialect" => PossibleValue::new(name).alias("cpp"),
+            _ => PossibleValue::new(name),
+        })
+    };
+    return vec![
+        Arg::new("rows")
+            .long("rows")
+            .action(ArgAction::Set)
+            .multiple_values(true)
+            .use_value_delimiter(true)
+            .require_value_delimiter(true)
+            .possible_values(axes())
+            .help("print a flat table with a row per value of these comma separated axes."),
+        Arg::new("cols")
+            .long("cols")
+            .action(ArgAction::Set)
+            .multiple_values(true)
+            .use_value_delimiter(true)
+            .require_value_delimiter(true)
+            .possible_values(axes())
+            .help("print a flat table with a column per value of these comma separated axes."),
+        Arg::new("collapse-passing")
+            .long("collapse-passing")
+            .action(ArgAction::SetTrue)
+            .help("hide rows and columns of the flat table without failures."),
+        Arg::new("failing-only")
+            .long("failing-only")
+            .action(ArgAction::SetTrue)
+            .help("only show failing cells in the flat table."),
+    ];
+}
+
+fn html_arg<'a>() -> Arg<'a> {
+    return Arg::new("html")
+        .long("html")
+        .value_name("PATH")
+        .action(ArgAction::Set)
+        .help("write a self-contained HTML report of the run.");
+}
+
+fn progress_arg<'a>() -> Arg<'a> {
+    return Arg::new("progress")
+        .long("progress")
+        .action(ArgAction::Set)
+        .possible_values(progress::Mode::NAMES)
+        .default_value("auto")
+        .help("draw progress bars, print plain or GitHub Actions log lines, or nothing. auto draws bars on terminals only.");
+}
+
+fn events_arg<'a>() -> Arg<'a> {
+    return Arg::new("events")
+        .long("events")
+        .value_name("PATH")
+        .action(ArgAction::Set)
+        .help("write the events of the run as JSON lines to PATH, or to stdout instead of the summary for -.");
+}
+
+fn no_cache_arg<'a>() -> Arg<'a> {
+    return Arg::new("no-cache")
+        .long("no-cache")
+        .action(ArgAction::SetTrue)
+        .help(
+            "run cells even if they passed before with the same sources, toolchains and options.",
+        );
+}
+
+// Arguments repeating the cells of an earlier run that did not pass.
+fn rerun_args<'a>() -> Vec<Arg<'a>> {
+    return vec![
+        Arg::new("only-failed")
+            .long("only-failed")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("only-not-passed")
+            .help("run only the cells that failed in the last run of this subcommand."),
+        Arg::new("only-not-passed")
+            .long("only-not-passed")
+            .action(ArgAction::SetTrue)
+            .help(
+                "run only the cells that failed or did not run in the last run of this subcommand.",
+            ),
+        Arg::new("from-run")
+            .long("from-run")
+            .value_name("ID")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(u64))
+            .help("with --only-failed or --only-not-passed, take the cells from this run of the same subcommand instead."),
+    ];
+}
+
+// Arguments controlling how matrix cells are scheduled, shared by every
+// subcommand that runs the matrix.
+fn scheduling_args<'a>() -> Vec<Arg<'a>> {
+    return vec![
+        Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(usize))
+            .help("cap the total number of parallel jobs across all builds."),
+        Arg::new("mem-per-job")
+            .long("mem-per-job")
+            .action(ArgAction::Set)
+            .value_parser(memory::parse_size)
+            .help("memory estimate for a single compile job, e.g. 4G."),
+        Arg::new("mem-budget")
+            .long("mem-budget")
+            .action(ArgAction::Set)
+            .value_parser(memory::parse_size)
+            .help("memory all concurrent jobs may use together, e.g. 200G."),
+        Arg::new("fail-fast")
+            .long("fail-fast")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(&["keep-going", "max-failures"])
+            .help("cancel all remaining and running cells on the first failure."),
+        Arg::new("max-failures")
+            .long("max-failures")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(usize))
+            .help("cancel the run once this many cells failed."),
+        Arg::new("keep-going")
+            .short('k')
+            .long("keep-going")
+            .action(ArgAction::SetTrue)
+            .help("let ninja build every target it can so each cell reports all broken ones."),
+    ];
+}
+
+fn single_graph_arg<'a>() -> Arg<'a> {
+    return Arg::new("single-graph")
+        .long("single-graph")
+        .action(ArgAction::SetTrue)
+        .help("build all cells through one top-level build.ninja and a single ninja.");
+}
+
+fn reconfigure_arg<'a>() -> Arg<'a> {
+    return Arg::new("reconfigure")
+        .long("reconfigure")
+        .action(ArgAction::SetTrue)
+        .help("run cmake even if a cell's cache is up to date.");
+}
+
+// Per-phase timeouts, shared by every subcommand that runs cmake, ninja or ctest.
+fn timeout_args<'a>() -> Vec<Arg<'a>> {
+    let timeout = |id: &'a str, help: &'a str| -> Arg<'a> {
+        return Arg::new(id)
+            .long(id)
+            .action(ArgAction::Set)
+            .value_parser(process::parse_duration)
+            .help(help);
+    };
+
+    return vec![
+        timeout("configure-timeout", "kill cmake after this long, e.g. 10m."),
+        timeout(
+            "build-timeout",
+            "kill a cell's build after this long, e.g. 2h.",
+        ),
+        timeout(
+            "test-timeout",
+            "kill a cell's ctest run after this long, e.g. 1h.",
+        ),
+        timeout(
+            "per-test-timeout",
+            "timeout for every single test, e.g. 10m.",
+        ),
+        timeout(
+            "stall-timeout",
+            "kill any phase that produced no output for this long, e.g. 20m.",
+        ),
+    ];
+}
+
+fn get_timeouts(config: &AppConfig, matches: &ArgMatches) -> Timeouts {
+    let timeout = |arg: &str, value: &Option<String>| -> Option<Duration> {
+        if let Ok(Some(duration)) = matches.try_get_one::<Duration>(arg) {
+            return Some(*duration);
+        }
+        return value
+            .as_ref()
+            .map(|v| process::parse_duration(v).expect("validated when loading the config"));
+    };
+
+    let timeouts = &config.timeouts;
+    return Timeouts {
+        configure: timeout("configure-timeout", &timeouts.configure),
+        build: timeout("build-timeout", &timeouts.build),
+        test: timeout("test-timeout", &timeouts.test),
+        per_test: timeout("per-test-timeout", &timeouts.per_test),
+        stall: timeout("stall-timeout", &timeouts.stall),
+    };
+}
+
+// Value of a boolean flag, false for flags the subcommand does not define.
+fn flag(matches: &ArgMatches, id: &str) -> bool {
+    return matches
+        .try_get_one::<bool>(id)
+        .ok()
+        .flatten()
+        .copied()
+        .unwrap_or(false);
+}
+
+fn get_max_failures(matches: &ArgMatches) -> Option<usize> {
+    if flag(matches, "fail-fast") {
+        return Some(1);
     }
+    return matches.get_one::<usize>("max-failures").copied();
+}
+
+fn get_jobs(matches: &ArgMatches) -> usize {
+    return match matches.get_one::<usize>("jobs") {
+        Some(jobs) => std::cmp::max(*jobs, 1),
+        None => std::thread::available_parallelism().unwrap().get(),
+    };
+}
+
+// Resolves the per-job memory estimate and the global memory budget. Command
+// line values win over the config file; without either the estimate is learned
+// from the peak RSS of the previous run and the budget derived from
+// /proc/meminfo.
+fn get_memory_limits(config: &AppConfig, matches: &ArgMatches, state_dir: &Path) -> (u64, u64) {
+    let configured = |arg: &str, value: &Option<String>| -> Option<u64> {
+        if let Some(size) = matches.get_one::<u64>(arg) {
+            return Some(*size);
+        }
+        return value
+            .as_ref()
+            .map(|v| memory::parse_size(v).expect("validated when loading the config"));
+    };
+
+    let per_job = configured("mem-per-job", &config.memory.per_job)
+        .or_else(|| memory::load_learned(state_dir))
+        .unwrap_or(memory::DEFAULT_PER_JOB);
+
+    let budget = configured("mem-budget", &config.memory.budget)
+        .unwrap_or_else(|| memory::meminfo().map_or(u64::MAX, |info| info.total / 10 * 9));
+
+    return (per_job, budget);
 }
 
-fn get_build_types(matches: &ArgMatches) -> Vec<&str> {
-    if matches.contains_id("types") {
+fn selected<'a>(
+    matches: &'a ArgMatches,
+    id: &str,
+    defaults: &'a Option<Vec<String>>,
+    all: Vec<&'a str>,
+) -> Vec<&'a str> {
+    if matches.contains_id(id) {
         return matches
-            .get_many::<String>("types")
+            .get_many::<String>(id)
             .expect("contains_id")
             .map(|s| s.as_str())
             .collect();
+    } else if let Some(defaults) = defaults {
+        return defaults.iter().map(String::as_str).collect();
     } else {
-        return vec!["debug", "release"];
+        return all;
     }
 }
 
+fn get_compilers<'a>(config: &'a AppConfig, matches: &'a ArgMatches) -> Vec<&'a str> {
+    return selected(
+        matches,
+        "compilers",
+        &config.defaults.compilers,
+        config.compiler_labels(),
+    );
+}
+
+fn get_build_types<'a>(config: &'a AppConfig, matches: &'a ArgMatches) -> Vec<&'a str> {
+    return selected(
+        matches,
+        "types",
+        &config.defaults.types,
+        BUILD_TYPES.to_vec(),
+    );
+}
+
 fn get_ctks<'a>(config: &'a AppConfig, matches: &'a ArgMatches) -> Vec<&'a str> {
-    if matches.contains_id("ctks") {
-        return matches
-            .get_many::<String>("ctks")
-            .expect("contains_id")
-            .map(|s| s.as_str())
-            .collect();
-    } else {
-        return config.ctk_labels();
+    return selected(matches, "ctks", &config.defaults.ctks, config.ctk_labels());
+}
+
+fn get_dialects<'a>(config: &'a AppConfig, matches: &'a ArgMatches) -> Vec<&'a str> {
+    return selected(
+        matches,
+        "dialects",
+        &config.defaults.dialects,
+        DIALECTS.to_vec(),
+    );
+}
+
+type CellCoordinates = (String, String, String, String);
+
+// Cells of an earlier run picked by `--only-failed` or `--only-not-passed`.
+struct Rerun {
+    run: history::Run,
+    // (build type, ctk, compiler, dialect)
+    cells: HashSet<CellCoordinates>,
+}
+
+// Picks the cells to repeat from `--from-run`, or from the last run of the
+// same subcommand.
+fn get_rerun(
+    matches: &ArgMatches,
+    state_dir: &Path,
+    action: &str,
+) -> Result<Option<Rerun>, String> {
+    let only_failed = flag(matches, "only-failed");
+    let only_not_passed = flag(matches, "only-not-passed");
+    let from_run = matches.try_get_one::<u64>("from-run").ok().flatten();
+    if !only_failed && !only_not_passed {
+        if from_run.is_some() {
+            return Err("--from-run needs --only-failed or --only-not-passed".to_string());
+        }
+        return Ok(None);
     }
+
+    let run = match from_run {
+        Some(id) => {
+            let run =
+                history::find(state_dir, *id).ok_or(format!("No run {} in the history", id))?;
+            // The cells failed in the phases of that subcommand
+            if run.action != action {
+                return Err(format!(
+                    "Run {} was a {} run, rerun its cells with {}",
+                    id, run.action, run.action
+                ));
+            }
+            run
+        }
+        None => history::load(state_dir)
+            .into_iter()
+            .rev()
+            .find(|run| run.action == action)
+            .ok_or(format!("No previous {} run in the history", action))?,
+    };
+
+    let cells: HashSet<CellCoordinates> = run
+        .cells
+        .iter()
+        .filter(|cell| match cell.outcome {
+            Outcome::Failed | Outcome::TimedOut => true,
+            Outcome::Interrupted | Outcome::NotRun => only_not_passed,
+            Outcome::Passed | Outcome::Cached => false,
+        })
+        .map(|cell| {
+            (
+                cell.build_type.clone(),
+                cell.ctk.clone(),
+                cell.compiler.clone(),
+                cell.cpp.clone(),
+            )
+        })
+        .collect();
+
+    return Ok(Some(Rerun { run, cells }));
+}
+
+// Values of an axis the cells to rerun have, in configured order, unless the
+// axis was given on the command line. Neither the configured defaults nor the
+// full axis should drop or add cells to a rerun.
+fn rerun_axis<'a>(
+    matches: &ArgMatches,
+    id: &str,
+    rerun: Option<&Rerun>,
+    all: Vec<&'a str>,
+    selected: Vec<&'a str>,
+    value: fn(&CellCoordinates) -> &String,
+) -> Vec<&'a str> {
+    return match rerun {
+        Some(rerun) if !matches.contains_id(id) => all
+            .into_iter()
+            .filter(|v| rerun.cells.iter().any(|cell| value(cell) == v))
+            .collect(),
+        _ => selected,
+    };
 }
 
-fn get_dialects(matches: &ArgMatches) -> Vec<&str> {
-    if matches.contains_id("dialects") {
+fn get_format(matches: &ArgMatches) -> report::Format {
+    return matches
+        .try_get_one::<String>("format")
+        .ok()
+        .flatten()
+        .and_then(|format| report::Format::parse(format))
+        .unwrap_or(report::Format::Table);
+}
+
+// The flat table layout if any of its arguments was given. Axes missing from
+// `--rows` and `--cols` are added to the rows, or to the columns when only
+// `--rows` was given.
+fn get_layout(matches: &ArgMatches) -> Result<Option<report::Layout>, String> {
+    let axes = |id: &str| -> Option<Vec<report::Axis>> {
         return matches
-            .get_many::<String>("dialects")
-            .expect("contains_id")
-            .map(|s| s.as_str())
-            .collect();
-    } else {
-        return vec!["11", "14", "17"];
+            .try_get_many::<String>(id)
+            .ok()
+            .flatten()
+            .map(|names| names.filter_map(|name| report::Axis::parse(name)).collect());
+    };
+    let flag = |id: &str| matches.try_get_one::<bool>(id).ok().flatten() == Some(&true);
+
+    let (rows, cols) = (axes("rows"), axes("cols"));
+    let collapse = flag("collapse-passing");
+    let failing_only = flag("failing-only");
+    if rows.is_none() && cols.is_none() && !collapse && !failing_only {
+        return Ok(None);
+    }
+
+    let only_rows = rows.is_some() && cols.is_none();
+    let mut rows = rows.unwrap_or_default();
+    let mut cols = cols.unwrap_or_else(|| {
+        if only_rows {
+            Vec::new()
+        } else {
+            vec![report::Axis::Dialect]
+        }
+    });
+    if let Some(axis) = rows.iter().find(|axis| cols.contains(axis)) {
+        return Err(format!("{} is used by both --rows and --cols", axis.name()));
+    }
+    for name in report::Axis::NAMES {
+        let axis = report::Axis::parse(name).unwrap();
+        if rows.contains(&axis) || cols.contains(&axis) {
+            continue;
+        }
+        if only_rows {
+            cols.push(axis);
+        } else {
+            rows.push(axis);
+        }
+    }
+    if rows.is_empty() || cols.is_empty() {
+        return Err("--rows and --cols each need at least one axis".to_string());
     }
+
+    return Ok(Some(report::Layout {
+        rows,
+        cols,
+        collapse,
+        failing_only,
+    }));
 }
 
-fn get_targets(cpp: &Vec<&str>, matches: &ArgMatches) -> HashMap<String, String> {
-    let mut result: HashMap<String, String> = HashMap::new();
+fn get_progress(matches: &ArgMatches) -> progress::Mode {
+    return matches
+        .try_get_one::<String>("progress")
+        .ok()
+        .flatten()
+        .and_then(|mode| progress::Mode::parse(mode))
+        .unwrap_or(progress::Mode::Bars);
+}
 
-    if matches.contains_id("targets") {
-        let targets: Vec<_> = matches
+fn get_target_names(matches: &ArgMatches) -> Vec<String> {
+    if matches.try_contains_id("targets").unwrap_or(false) {
+        return matches
             .get_many::<String>("targets")
             .expect("is present")
-            .map(|s| s.as_str())
+            .cloned()
             .collect();
+    }
+    return Vec::new();
+}
+
+fn get_targets(cpp: &Vec<&str>, targets: &[String]) -> HashMap<String, String> {
+    let mut result: HashMap<String, String> = HashMap::new();
 
+    if !targets.is_empty() {
         for target in targets.iter() {
             for dialect in cpp {
                 result.insert(
@@ -327,9 +932,120 @@ fn get_targets(cpp: &Vec<&str>, matches: &ArgMatches) -> HashMap<String, String>
     return result;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Outcome {
+    Passed,
+    Failed,
+    // A phase of the cell ran into its timeout or stopped producing output
+    TimedOut,
+    // The cell was running when the user cancelled the run
+    Interrupted,
+    NotRun,
+    // Passed in an earlier run with the same fingerprint and was skipped
+    Cached,
+}
+
+impl Outcome {
+    fn for_cell(state: &State, succeeded: bool) -> Self {
+        if succeeded {
+            return Outcome::Passed;
+        } else if state.report.lock().unwrap().timeout.is_some() {
+            return Outcome::TimedOut;
+        } else if cancel::requested() {
+            return Outcome::Interrupted;
+        } else {
+            return Outcome::Failed;
+        }
+    }
+
+    fn symbol(&self) -> ColoredString {
+        return match self {
+            Outcome::Passed => "✓".green(),
+            Outcome::Failed => "✗".red(),
+            Outcome::TimedOut => "T".magenta().bold(),
+            Outcome::Interrupted => "!".yellow(),
+            Outcome::NotRun => "-".dimmed(),
+            Outcome::Cached => "cached ✓".green(),
+        };
+    }
+
+    fn passed(&self) -> bool {
+        return matches!(self, Outcome::Passed | Outcome::Cached);
+    }
+
+    fn failed(&self) -> bool {
+        return matches!(self, Outcome::Failed | Outcome::TimedOut);
+    }
+
+    fn name(&self) -> &'static str {
+        return match self {
+            Outcome::Passed => "passed",
+            Outcome::Failed => "failed",
+            Outcome::TimedOut => "timed-out",
+            Outcome::Interrupted => "interrupted",
+            Outcome::NotRun => "not-run",
+            Outcome::Cached => "cached",
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Phase {
+    Configure,
+    Build,
+    Test,
+}
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        return match self {
+            Phase::Configure => "configure",
+            Phase::Build => "build",
+            Phase::Test => "test",
+        };
+    }
+}
+
+#[derive(Debug)]
+struct Timeout {
+    phase: Phase,
+    expiry: Expiry,
+    // Last lines of the phase's log before it was killed
+    tail: Vec<String>,
+}
+
+// Everything recorded about a cell besides its outcome.
+#[derive(Debug, Default)]
+struct CellReport {
+    diagnostics: Vec<Diagnostic>,
+    timeout: Option<Timeout>,
+    tests: Vec<TestCase>,
+    // Phase that stopped the cell's pipeline
+    failed_phase: Option<Phase>,
+    // Time spent running the cell's tools, in total and per phase
+    duration: Duration,
+    phase_durations: HashMap<Phase, Duration>,
+}
+
+impl CellReport {
+    fn phase_duration(&self, phase: Phase) -> Duration {
+        return self
+            .phase_durations
+            .get(&phase)
+            .copied()
+            .unwrap_or_default();
+    }
+}
+
+type CellKey<'a> = (&'a str, &'a str, &'a str, &'a str);
+type CppState<'a> = HashMap<&'a str, HashMap<&'a str, Outcome>>;
+type CtkState<'a> = HashMap<&'a str, CppState<'a>>;
+
 #[derive(Debug)]
 struct BuildResult<'a> {
-    data: HashMap<&'a str, HashMap<&'a str, HashMap<&'a str, HashMap<&'a str, bool>>>>,
+    data: HashMap<&'a str, CtkState<'a>>,
+    reports: HashMap<CellKey<'a>, CellReport>,
 }
 
 impl<'a> BuildResult<'a> {
@@ -339,35 +1055,41 @@ impl<'a> BuildResult<'a> {
         cpp: &Vec<&'a str>,
         compilers: &Vec<&'a str>,
     ) -> Self {
-        let mut compilers_state: Vec<(&'a str, bool)> = Vec::new();
+        let mut compilers_state: Vec<(&'a str, Outcome)> = Vec::new();
         for compiler in compilers {
-            compilers_state.push((compiler, false));
+            compilers_state.push((compiler, Outcome::NotRun));
         }
-        let compilers_state: HashMap<&'a str, bool> = compilers_state.into_iter().collect();
+        let compilers_state: HashMap<&'a str, Outcome> = compilers_state.into_iter().collect();
 
-        let mut cpp_state: HashMap<&'a str, HashMap<&'a str, bool>> = HashMap::new();
+        let mut cpp_state: CppState<'a> = HashMap::new();
         for dialect in cpp {
             cpp_state.insert(dialect, compilers_state.clone());
         }
 
-        let mut ctk_state: HashMap<&'a str, HashMap<&'a str, HashMap<&'a str, bool>>> =
-            HashMap::new();
+        let mut ctk_state: CtkState<'a> = HashMap::new();
         for ctk in ctks {
             ctk_state.insert(ctk, cpp_state.clone());
         }
 
-        let mut type_state: HashMap<
-            &'a str,
-            HashMap<&'a str, HashMap<&'a str, HashMap<&'a str, bool>>>,
-        > = HashMap::new();
+        let mut type_state: HashMap<&'a str, CtkState<'a>> = HashMap::new();
         for build_type in types {
             type_state.insert(build_type, ctk_state.clone());
         }
 
-        return Self { data: type_state };
+        return Self {
+            data: type_state,
+            reports: HashMap::new(),
+        };
     }
 
-    fn success(&mut self, build_type: &'a str, ctk: &'a str, cpp: &'a str, compiler: &'a str) {
+    fn set(
+        &mut self,
+        build_type: &'a str,
+        ctk: &'a str,
+        cpp: &'a str,
+        compiler: &'a str,
+        outcome: Outcome,
+    ) {
         *self
             .data
             .get_mut(build_type)
@@ -377,17 +1099,39 @@ impl<'a> BuildResult<'a> {
             .get_mut(cpp)
             .unwrap()
             .get_mut(compiler)
-            .unwrap() = true;
+            .unwrap() = outcome;
+    }
+
+    fn report(
+        &mut self,
+        build_type: &'a str,
+        ctk: &'a str,
+        cpp: &'a str,
+        compiler: &'a str,
+        report: CellReport,
+    ) {
+        self.reports
+            .insert((build_type, ctk, cpp, compiler), report);
     }
 
-    fn status(
+    fn get_report(
         &self,
         build_type: &'a str,
         ctk: &'a str,
         cpp: &'a str,
         compiler: &'a str,
-    ) -> ColoredString {
-        if *self
+    ) -> Option<&CellReport> {
+        return self.reports.get(&(build_type, ctk, cpp, compiler));
+    }
+
+    // Summary table entry of a cell and whether it failed.
+    fn status(&self, build_type: &str, ctk: &str, cpp: &str, compiler: &str) -> (String, bool) {
+        let outcome = self.outcome(build_type, ctk, cpp, compiler);
+        return (outcome.symbol().to_string(), outcome.failed());
+    }
+
+    fn outcome(&self, build_type: &str, ctk: &str, cpp: &str, compiler: &str) -> Outcome {
+        return *self
             .data
             .get(build_type)
             .unwrap()
@@ -396,17 +1140,20 @@ impl<'a> BuildResult<'a> {
             .get(cpp)
             .unwrap()
             .get(compiler)
-            .unwrap()
-        {
-            return "✓".green();
-        } else {
-            return "✗".red();
-        }
+            .unwrap();
     }
 }
 
 trait Action {
+    // Subcommand performing the action, recorded in the run history
+    const NAME: &'static str;
+
     fn do_action(state: &State) -> bool;
+
+    // Work left for a cell once it was built as part of the global build graph.
+    fn after_build(_state: &State) -> bool {
+        return true;
+    }
 }
 
 struct Configure {}
@@ -414,14 +1161,10 @@ struct Build {}
 struct Test {}
 struct Clean {}
 
-impl Action for Configure {
-    fn do_action(state: &State) -> bool {
-        let cxx_path = state
-            .config
-            .compilers
-            .get(&state.compiler.to_string())
-            .unwrap()
-            .clone();
+impl Configure {
+    // Arguments cmake is run with for the cell.
+    fn arguments(state: &State) -> Vec<String> {
+        let cxx_path = state.config.compilers.get(state.compiler).unwrap().clone();
 
         let cub_path = state.config.src.get("cub").unwrap();
         let thrust_path = state.config.src.get("thrust").unwrap();
@@ -436,6 +1179,10 @@ impl Action for Configure {
         arguments.push("-DCUB_IGNORE_DEPRECATED_CPP_DIALECT=ON".to_string());
         arguments.push("-DCMAKE_EXPORT_COMPILE_COMMANDS=ON".to_string());
 
+        if let Some(prefix) = &state.output_prefix {
+            arguments.push(format!("-DCMAKE_NINJA_OUTPUT_PATH_PREFIX={}", prefix));
+        }
+
         if state.compiler.contains("nvhpc") {
             // TODO Push ctk version
             // TODO -DCMAKE_CUDA_FLAGS="-gpu=cuda11.6 -gpu=cc86"
@@ -443,116 +1190,322 @@ impl Action for Configure {
             arguments.push(format!("-DCMAKE_CUDA_COMPILER={}", &cxx_path).to_string());
             arguments.push("-DCMAKE_CUDA_COMPILER_ID=NVCXX".to_string());
         } else {
-            let ctk_path = state
-                .config
-                .ctks
-                .get(&state.ctk.to_string())
-                .unwrap()
-                .clone();
+            let ctk_path = state.config.ctks.get(state.ctk).unwrap().clone();
             let nvcc_path = Path::new(&ctk_path).join("bin").join("nvcc");
-            let nvcc_path_str = nvcc_path.to_str().unwrap().clone();
+            let nvcc_path_str = nvcc_path.to_str().unwrap();
             arguments.push(format!("-DCMAKE_CUDA_COMPILER={}", nvcc_path_str).to_string());
             arguments.push(format!("-DCMAKE_CXX_COMPILER={}", &cxx_path));
         }
 
-        for d in ["11", "14", "17"] {
+        for d in DIALECTS {
             if d == state.cpp {
-                arguments.push(format!("-DCUB_ENABLE_DIALECT_CPP{}=ON", d.to_string()).to_string());
+                arguments.push(format!("-DCUB_ENABLE_DIALECT_CPP{}=ON", d));
             } else {
-                arguments
-                    .push(format!("-DCUB_ENABLE_DIALECT_CPP{}=OFF", d.to_string()).to_string());
+                arguments.push(format!("-DCUB_ENABLE_DIALECT_CPP{}=OFF", d));
             }
         }
         arguments.push(format!("-DThrust_DIR={}/thrust/cmake", thrust_path).to_string());
         arguments.push("-DCUB_ENABLE_TESTS_WITH_RDC=OFF".to_string());
         arguments.push(cub_path.clone());
 
-        let cmake_output = ProcCommand::new("cmake")
-            .args(arguments)
-            .output()
-            .expect("failed to execute cmake process");
+        return arguments;
+    }
+}
 
-        if !cmake_output.status.success() {
-            // println!(
-            //     "stderr 1: {}",
-            //     String::from_utf8_lossy(&cmake_output.stderr)
-            // );
-            return false;
+impl Action for Configure {
+    const NAME: &'static str = "config";
+
+    fn do_action(state: &State) -> bool {
+        let arguments = Configure::arguments(state);
+
+        let build_dir = Path::new(&state.build_dir);
+        let unchanged = manifest::load(build_dir).is_some_and(|m| m.cmake_args == arguments);
+        if !state.reconfigure && unchanged && cmake::is_up_to_date(build_dir, &arguments) {
+            fs::write(
+                build_dir.join(format!("{}.log", Phase::Configure.name())),
+                "# cache is up to date, cmake skipped\n",
+            )
+            .ok();
+            return true;
         }
 
-        return true;
+        let mut cmake = ProcCommand::new("cmake");
+        cmake.args(&arguments);
+
+        let configured = run_logged(state, Phase::Configure, &mut cmake, |_| {});
+        if configured {
+            let manifest = manifest::Manifest {
+                cmake_args: arguments,
+            };
+            manifest::save(build_dir, &manifest).ok();
+        } else {
+            manifest::remove(build_dir);
+        }
+
+        return configured;
     }
 }
 
 impl Action for Build {
+    const NAME: &'static str = "build";
+
     fn do_action(state: &State) -> bool {
-        if !Configure::do_action(&state) {
+        if !Configure::do_action(state) || cancel::requested() {
             return false;
         }
 
-        let re = Regex::new(r"^\[(?P<current>\d+)/(?P<total>\d+)\]").unwrap();
+        let re = graph::status_regex();
 
         let mut arguments: Vec<String> = Vec::new();
-        arguments.push(format!("-C{}", &state.build_dir).to_string());
-        arguments.push(format!("-j{}", state.num_threads_per_build).to_string());
+        arguments.push(format!("-C{}", &state.build_dir));
+
+        if state.keep_going {
+            arguments.push("-k0".to_string());
+        }
+
+        // Ninja only consults the jobserver when no explicit -j is given
+        if state.jobserver.is_none() {
+            arguments.push(format!("-j{}", state.num_threads_per_build));
+        }
 
-        let tgt = state.targets.get(&state.cpp.to_string()).unwrap();
+        let tgt = state.targets.get(state.cpp).unwrap();
 
         if !tgt.is_empty() {
             arguments.push(tgt.to_string());
         }
 
-        let mut ninja_child = ProcCommand::new("ninja")
-            .args(arguments)
-            .stdout(Stdio::piped())
-            .spawn()
-            .expect("failed to execute ninja process");
+        let mut ninja = ProcCommand::new("ninja");
+        ninja.args(arguments);
 
-        loop {
-            {
-                let mut f = BufReader::new(ninja_child.stdout.as_mut().unwrap());
-                let mut buf = String::new();
-                match f.read_line(&mut buf) {
-                    Ok(_) => {
-                        if buf.is_empty() {
-                            // println!("empty line, exit");
-                        } else {
-                            match re.captures(&buf) {
-                                Some(caps) => {
-                                    let current: u64 = caps["current"].parse().unwrap();
-                                    let total: u64 = caps["total"].parse().unwrap();
-                                    state.pb.set_length(total);
-                                    state.pb.set_position(current);
-                                }
-                                None => {}
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("an error!: {:?}", e);
-                        break;
-                    }
-                }
-            }
+        if let Some(jobserver) = state.jobserver {
+            jobserver.configure(&mut ninja);
+        }
+
+        let label = state.label();
+        return run_logged(state, Phase::Build, &mut ninja, |line| {
+            if let Some(caps) = re.captures(line) {
+                let current: u64 = caps["current"].parse().unwrap();
+                let total: u64 = caps["total"].parse().unwrap();
+                state.pb.set_length(total);
+                state.pb.set_position(current);
+                state
+                    .progress
+                    .progress(&label, Phase::Build.name(), current, total);
+            }
+        });
+    }
+}
 
-            match ninja_child.try_wait() {
-                Ok(Some(status)) => {
-                    if status.success() {
-                        return true;
+impl Build {
+    // Builds every configured cell with one ninja invocation over a top-level
+    // build.ninja that includes each cell's graph via subninja, so all cells
+    // share a single job queue. Output is attributed to cells by their output
+    // path prefix. Returns the outcome of building each cell, NotRun for those
+    // that were not configured.
+    fn do_graph(
+        states: &[State],
+        configured: &[bool],
+        build_root: &Path,
+        pool: &JobServer,
+        jobs: usize,
+    ) -> Vec<Outcome> {
+        let mut built = vec![Outcome::NotRun; states.len()];
+
+        let members: Vec<&State> = states
+            .iter()
+            .zip(configured)
+            .filter(|(_, configured)| **configured)
+            .map(|(state, _)| state)
+            .collect();
+        if members.is_empty() || cancel::requested() {
+            return built;
+        }
+
+        let prefixes: Vec<&str> = members
+            .iter()
+            .map(|state| state.output_prefix.as_deref().unwrap())
+            .collect();
+        graph::write_build_file(build_root, &prefixes).expect("failed to write build.ninja");
+
+        let targets: Vec<String> = members
+            .iter()
+            .zip(&prefixes)
+            .map(|(state, prefix)| {
+                let tgt = state.targets.get(state.cpp).unwrap();
+                if tgt.is_empty() {
+                    format!("{}all", prefix)
+                } else {
+                    format!("{}{}", prefix, tgt)
+                }
+            })
+            .collect();
+
+        for (state, total) in members
+            .iter()
+            .zip(graph::count_edges(build_root, &targets, &prefixes))
+        {
+            state.pb.set_length(total);
+            state.pb.set_position(0);
+        }
+
+        let mut arguments: Vec<String> = Vec::new();
+        arguments.push(format!("-C{}", build_root.display()));
+        arguments.push("-k0".to_string());
+
+        let jobserver = members[0].jobserver;
+        if jobserver.is_none() {
+            arguments.push(format!("-j{}", jobs));
+        }
+        arguments.extend(targets);
+
+        let mut ninja = ProcCommand::new("ninja");
+        ninja.args(arguments);
+
+        if let Some(jobserver) = jobserver {
+            jobserver.configure(&mut ninja);
+        }
+
+        let re = graph::status_regex();
+        let parser = diagnostics::Parser::new();
+        let mut logs: Vec<Option<fs::File>> = members
+            .iter()
+            .map(|state| fs::File::create(Path::new(&state.build_dir).join("build.log")).ok())
+            .collect();
+        let mut tails: Vec<VecDeque<String>> = vec![VecDeque::new(); members.len()];
+        let mut failed = vec![false; members.len()];
+        let mut current: Option<usize> = None;
+
+        // Per-cell build timeouts do not map onto the shared graph, only the
+        // stall watchdog applies to it
+        let limits = process::Limits {
+            timeout: None,
+            stall: members[0].timeouts.stall,
+        };
+
+        let _token = pool.acquire().expect("failed to acquire job token");
+        let log_path = build_root.join("build.log");
+        let labels: Vec<String> = members.iter().map(|state| state.label()).collect();
+        for (state, label) in members.iter().zip(&labels) {
+            state
+                .progress
+                .started(label, Phase::Build.name(), &state.pb);
+        }
+        let started = Instant::now();
+        let finished = process::run(&mut ninja, &log_path, &limits, |_, line| {
+            let failure = line.starts_with("FAILED: ");
+            if failure || re.is_match(line) {
+                current = graph::owner(line, &prefixes);
+                if let Some(i) = current {
+                    if failure {
+                        if !failed[i] {
+                            members[i].failures.record(Outcome::Failed);
+                        }
+                        failed[i] = true;
+                    } else {
+                        let pb = &members[i].pb;
+                        pb.inc(1);
+                        members[i].progress.progress(
+                            &labels[i],
+                            Phase::Build.name(),
+                            pb.position(),
+                            pb.length().unwrap_or(0),
+                        );
                     }
-                    break;
                 }
-                Ok(None) => {
-                    // println!("status not ready yet");
+            }
+
+            // Tool output follows the status or FAILED line of its edge
+            if let Some(i) = current {
+                if let Some(log) = &mut logs[i] {
+                    writeln!(log, "{}", line).ok();
                 }
-                Err(e) => println!("error attempting to wait: {e}"),
+                if let Some(diagnostic) = parser.parse(line) {
+                    members[i].progress.diagnostic(&labels[i], &diagnostic);
+                    members[i]
+                        .report
+                        .lock()
+                        .unwrap()
+                        .diagnostics
+                        .push(diagnostic);
+                }
+                if tails[i].len() == TIMEOUT_TAIL_LINES {
+                    tails[i].pop_front();
+                }
+                tails[i].push_back(line.to_string());
             }
+        });
+
+        // Every cell spent the whole shared build in the graph
+        let elapsed = started.elapsed();
+        for state in &members {
+            let mut report = state.report.lock().unwrap();
+            report.duration += elapsed;
+            *report.phase_durations.entry(Phase::Build).or_default() += elapsed;
         }
-        return false;
+
+        if let Ok(Finished {
+            expired: Some(expiry),
+            ..
+        }) = &finished
+        {
+            for ((state, tail), failed) in members.iter().zip(tails).zip(&failed) {
+                if !failed {
+                    state.report.lock().unwrap().timeout = Some(Timeout {
+                        phase: Phase::Build,
+                        expiry: *expiry,
+                        tail: tail.into(),
+                    });
+                }
+            }
+        }
+
+        // With -k0 ninja builds everything it can, so cells without failures
+        // are complete. A failure no cell owns (e.g. a broken manifest) or a
+        // killed ninja means nothing can be trusted to be built.
+        let succeeded = matches!(&finished, Ok(finished) if finished.success());
+        let killed = cancel::requested() || matches!(&finished, Ok(f) if f.expired.is_some());
+        let attributed = failed.iter().any(|f| *f);
+        let complete = succeeded || (attributed && !killed);
+
+        let mut member = 0;
+        for (i, configured) in configured.iter().enumerate() {
+            if *configured {
+                // A cell that failed also cancels the others with --fail-fast,
+                // only they were interrupted
+                built[i] = if failed[member] {
+                    Outcome::Failed
+                } else {
+                    Outcome::for_cell(members[member], complete)
+                };
+                members[member].progress.finished(
+                    &labels[member],
+                    Phase::Build.name(),
+                    built[i] == Outcome::Passed,
+                );
+                if built[i] != Outcome::Passed {
+                    let duration = {
+                        let mut report = members[member].report.lock().unwrap();
+                        report.failed_phase = Some(Phase::Build);
+                        report.duration
+                    };
+                    members[member].progress.finished_cell(
+                        &labels[member],
+                        &members[member].pb,
+                        built[i],
+                        duration,
+                    );
+                }
+                member += 1;
+            }
+        }
+
+        return built;
     }
 }
 
 impl Action for Clean {
+    const NAME: &'static str = "clean";
+
     fn do_action(state: &State) -> bool {
         let mut arguments: Vec<String> = Vec::new();
         arguments.push(format!("-C{}", &state.build_dir).to_string());
@@ -564,151 +1517,850 @@ impl Action for Clean {
             .output()
             .expect("failed to execute ninja process");
 
+        // Without a manifest the next build does not reuse cached results
+        // for the cleaned cell
+        manifest::remove(Path::new(&state.build_dir));
         return ninja_child.status.success();
     }
 }
 
 impl Action for Test {
+    const NAME: &'static str = "test";
+
     fn do_action(state: &State) -> bool {
-        if !Build::do_action(&state) {
+        if !Build::do_action(state) || cancel::requested() {
             return false;
         }
 
+        return Test::after_build(state);
+    }
+
+    fn after_build(state: &State) -> bool {
         let mut arguments: Vec<String> = Vec::new();
         arguments.push("--test-dir".to_string());
         arguments.push(state.build_dir.to_string());
 
-        let tgt = state.targets.get(&state.cpp.to_string()).unwrap();
+        let tgt = state.targets.get(state.cpp).unwrap();
 
         if !tgt.is_empty() {
             arguments.push("-R".to_string());
-            arguments.push(format!(".*{}.*", tgt).to_string());
+            arguments.push(format!(".*{}.*", tgt));
         }
 
-        let ninja_child = ProcCommand::new("ctest")
-            .args(arguments)
-            .output()
-            .expect("failed to execute ninja process");
+        if state.ctest_junit {
+            arguments.push("--output-junit".to_string());
+            let path = Path::new(&state.build_dir).join(ctest::JUNIT_FILE);
+            arguments.push(path.display().to_string());
+        }
 
-        return ninja_child.status.success();
+        if let Some(timeout) = state.timeouts.per_test {
+            arguments.push("--timeout".to_string());
+            arguments.push(timeout.as_secs().to_string());
+        }
+
+        let mut ctest = ProcCommand::new("ctest");
+        ctest.args(arguments);
+
+        let parser = ctest::Parser::new();
+        let label = state.label();
+        state.pb.set_position(0);
+        return run_logged(state, Phase::Test, &mut ctest, |line| {
+            if let Some(progress) = parser.parse(line) {
+                state.pb.set_length(progress.total);
+                state.pb.set_position(progress.current);
+                state.progress.progress(
+                    &label,
+                    Phase::Test.name(),
+                    progress.current,
+                    progress.total,
+                );
+                state.progress.test(&label, &progress.test);
+                state.report.lock().unwrap().tests.push(progress.test);
+            }
+        });
+    }
+}
+
+// Runs a phase of the cell's pipeline, logging its output to `<phase>.log`
+// inside the build directory and collecting any diagnostics it reports. A phase
+// exceeding its limits is killed and recorded as a timeout.
+fn run_logged<F>(state: &State, phase: Phase, cmd: &mut ProcCommand, mut on_line: F) -> bool
+where
+    F: FnMut(&str),
+{
+    let parser = diagnostics::Parser::new();
+    let log_path = Path::new(&state.build_dir).join(format!("{}.log", phase.name()));
+    let mut tail: VecDeque<String> = VecDeque::new();
+
+    let label = state.label();
+    state.progress.started(&label, phase.name(), &state.pb);
+    let started = Instant::now();
+    let finished = process::run(cmd, &log_path, &state.timeouts.limits(phase), |_, line| {
+        if let Some(diagnostic) = parser.parse(line) {
+            state.progress.diagnostic(&label, &diagnostic);
+            state.report.lock().unwrap().diagnostics.push(diagnostic);
+        }
+        if tail.len() == TIMEOUT_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line.to_string());
+        on_line(line);
+    });
+
+    let succeeded = match finished {
+        Ok(finished) => {
+            if let Some(expiry) = finished.expired {
+                state.report.lock().unwrap().timeout = Some(Timeout {
+                    phase,
+                    expiry,
+                    tail: tail.into(),
+                });
+            }
+            finished.success()
+        }
+        Err(_) => false,
+    };
+
+    state.progress.finished(&label, phase.name(), succeeded);
+    let mut report = state.report.lock().unwrap();
+    *report.phase_durations.entry(phase).or_default() += started.elapsed();
+    if !succeeded {
+        report.failed_phase = Some(phase);
     }
+    return succeeded;
 }
 
 struct State<'a> {
     config: &'a AppConfig,
     targets: &'a HashMap<String, String>,
-    pb: &'a ProgressBar,
+    pb: ProgressBar,
     build_dir: String,
     build_type: &'a str,
     ctk: &'a str,
     compiler: &'a str,
     cpp: &'a str,
     num_threads_per_build: usize,
+    jobserver: Option<&'a JobServer>,
+    output_prefix: Option<String>,
+    timeouts: &'a Timeouts,
+    keep_going: bool,
+    reconfigure: bool,
+    ctest_junit: bool,
+    failures: &'a FailureBudget,
+    progress: &'a progress::Reporter,
+    report: Mutex<CellReport>,
+    // Identifies the inputs of a build or test, see `cell_fingerprint`
+    fingerprint: Option<String>,
+}
+
+impl State<'_> {
+    fn label(&self) -> String {
+        return report::cell_label(&(self.build_type, self.ctk, self.cpp, self.compiler));
+    }
+}
+
+// Cancels the run once the number of failed cells reaches `max`.
+struct FailureBudget {
+    max: Option<usize>,
+    failures: AtomicUsize,
 }
 
-fn perform<T: Action>(config: &AppConfig, matches: &ArgMatches) {
-    let types = get_build_types(&matches);
-    let compilers = get_compilers(&config, &matches);
-    let ctks = get_ctks(&config, &matches);
-    let cpps = get_dialects(matches);
-    let targets = get_targets(&cpps, matches);
+impl FailureBudget {
+    fn record(&self, outcome: Outcome) {
+        if !matches!(outcome, Outcome::Failed | Outcome::TimedOut) {
+            return;
+        }
 
-    let num_builds = ctks.len() * compilers.len() * cpps.len() * types.len();
-    let results = Arc::new(Mutex::new(BuildResult::new(
-        &types, &ctks, &cpps, &compilers,
-    )));
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.max.is_some_and(|max| failures >= max) {
+            cancel::request(cancel::Reason::Failures);
+        }
+    }
+}
 
-    let num_cpus = std::thread::available_parallelism().unwrap().get();
-    let num_concurrent_builds = std::cmp::min(num_cpus, num_builds);
-    let num_threads_per_build = num_cpus / num_concurrent_builds;
+// Runs `action` for every selected cell in parallel. Each running cell holds a
+// job token covering the implicit job slot of its tools. No new cells start
+// once the run was cancelled. The pipeline of a cell ends when the action
+// fails or when it is the `last` one.
+fn run_cells<F>(
+    states: &[State],
+    pool: &JobServer,
+    selected: &[bool],
+    last: bool,
+    action: F,
+) -> Vec<Outcome>
+where
+    F: Fn(&State) -> bool + Sync,
+{
+    return states
+        .par_iter()
+        .zip(selected)
+        .map(|(state, selected)| {
+            if !selected {
+                return Outcome::NotRun;
+            }
+            let _token = pool.acquire().expect("failed to acquire job token");
+            if cancel::requested() {
+                return Outcome::NotRun;
+            }
+            let started = Instant::now();
+            let succeeded = action(state);
+            let duration = {
+                let mut report = state.report.lock().unwrap();
+                report.duration += started.elapsed();
+                report.duration
+            };
+            let outcome = Outcome::for_cell(state, succeeded);
+            state.failures.record(outcome);
+            if last || outcome != Outcome::Passed {
+                state
+                    .progress
+                    .finished_cell(&state.label(), &state.pb, outcome, duration);
+            }
+            outcome
+        })
+        .collect();
+}
 
-    println!("Build with {num_threads_per_build} threads per build and {num_concurrent_builds} concurrent builds");
+// Phase timings, timeout, failed tests and the end of the failed phase's log of
+// a cell, as shown in its GitHub Actions log group.
+fn cell_details(state: &State, report: &CellReport) -> Vec<String> {
+    let mut lines = vec![[Phase::Configure, Phase::Build, Phase::Test]
+        .iter()
+        .filter(|phase| report.phase_durations.contains_key(phase))
+        .map(|phase| {
+            format!(
+                "{} {}",
+                phase.name(),
+                process::format_duration(report.phase_duration(*phase))
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ")];
+    if let Some(timeout) = &report.timeout {
+        lines.push(format!("{} {}", timeout.phase.name(), timeout.expiry));
+    }
+    for test in &report.tests {
+        if matches!(test.status, TestStatus::Failed | TestStatus::Timeout) {
+            lines.push(format!("test {} {}", test.name, test.detail));
+        }
+    }
+    if let Some(phase) = report.failed_phase {
+        let log = Path::new(&state.build_dir).join(format!("{}.log", phase.name()));
+        lines.push(format!("last lines of {}:", log.display()));
+        lines.push(junit::log_excerpt(&log, GROUP_LOG_LINES));
+    }
+    return lines;
+}
 
-    rayon::scope(|s| {
-        let m = MultiProgress::new();
-        let sty = ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+// Builds the JUnit suite of a cell from ctest's own report, falling back to the
+// parsed ctest output, plus a synthetic failed case for a phase that broke.
+fn junit_suite(state: &State, outcome: Outcome) -> junit::Suite {
+    let report = state.report.lock().unwrap();
+    let build_dir = Path::new(&state.build_dir);
+    let mut suite = junit::Suite::new(state.label());
+
+    if !suite.merge_ctest(&build_dir.join(ctest::JUNIT_FILE)) {
+        for test in &report.tests {
+            let verdict = match test.status {
+                TestStatus::Passed => Verdict::Passed,
+                TestStatus::NotRun => Verdict::Skipped {
+                    message: &test.detail,
+                },
+                TestStatus::Failed | TestStatus::Timeout => Verdict::Failed {
+                    message: &test.detail,
+                    output: "",
+                },
+            };
+            suite.add_case(&test.name, test.duration, verdict);
+        }
+    }
+
+    match outcome {
+        Outcome::Failed | Outcome::TimedOut => {
+            if let Some(phase) = report.failed_phase {
+                if phase != Phase::Test || suite.is_empty() || report.timeout.is_some() {
+                    let message = match &report.timeout {
+                        Some(timeout) => format!("{} {}", phase.name(), timeout.expiry),
+                        None => format!("{} failed", phase.name()),
+                    };
+                    let log = build_dir.join(format!("{}.log", phase.name()));
+                    let output = junit::log_excerpt(&log, JUNIT_LOG_LINES);
+                    suite.add_case(
+                        phase.name(),
+                        Duration::ZERO,
+                        Verdict::Failed {
+                            message: &message,
+                            output: &output,
+                        },
+                    );
+                }
+            }
+        }
+        Outcome::Cached if suite.is_empty() => {
+            suite.add_case("cached", Duration::ZERO, Verdict::Passed);
+        }
+        Outcome::Interrupted | Outcome::NotRun if suite.is_empty() => {
+            let message = if outcome == Outcome::NotRun {
+                "not run"
+            } else {
+                "interrupted"
+            };
+            suite.add_case("run", Duration::ZERO, Verdict::Skipped { message });
+        }
+        _ => {}
+    }
+
+    return suite;
+}
+
+fn build_root() -> PathBuf {
+    return env::current_dir().unwrap().join("build");
+}
+
+// Composer state kept across runs, e.g. the run history and learned memory use.
+fn state_dir(build_root: &Path) -> PathBuf {
+    return build_root.join(".cccl-composer");
+}
+
+// Probes the selected compilers and the nvcc in each selected CTK, warning
+// about toolchains whose version is not the one their label names.
+fn probe_toolchains(
+    config: &AppConfig,
+    state_dir: &Path,
+    compilers: &[&str],
+    ctks: &[&str],
+) -> (
+    BTreeMap<String, toolchain::Probe>,
+    BTreeMap<String, toolchain::Probe>,
+) {
+    let mut cache = toolchain::Cache::load(state_dir);
+    let mut probe = |kind: &str, label: &str, path: PathBuf| {
+        let probe = cache.probe(&path);
+        match &probe {
+            Some(toolchain::Probe {
+                version: Some(version),
+                ..
+            }) if !toolchain::matches_label(label, version) => eprintln!(
+                "{}",
+                format!(
+                    "{} {} at {} is version {}",
+                    kind,
+                    label,
+                    path.display(),
+                    version
+                )
+                .yellow()
+            ),
+            Some(_) => {}
+            None => eprintln!(
+                "{}",
+                format!("Failed to probe {} {} at {}", kind, label, path.display()).yellow()
+            ),
+        }
+        return probe.map(|probe| (label.to_string(), probe));
+    };
+
+    let compiler_probes = compilers
+        .iter()
+        .filter_map(|label| {
+            let path = config.compilers.get(*label)?;
+            probe("Compiler", label, PathBuf::from(path))
+        })
+        .collect();
+    let ctk_probes = ctks
+        .iter()
+        .filter_map(|label| {
+            let path = config.ctks.get(*label)?;
+            probe("CTK", label, toolchain::nvcc(path))
+        })
+        .collect();
+    cache.save(state_dir);
+
+    return (compiler_probes, ctk_probes);
+}
+
+// Hashes everything the result of running `T` on the cell depends on: the
+// source trees including uncommitted changes, the toolchain versions, the
+// effective CMake options and the targets.
+fn cell_fingerprint<T: Action>(
+    state: &State,
+    sources: &[u8],
+    compiler_probes: &BTreeMap<String, toolchain::Probe>,
+    ctk_probes: &BTreeMap<String, toolchain::Probe>,
+) -> Option<String> {
+    let mut fingerprint = fingerprint::Fingerprint::new();
+    fingerprint
+        .add(T::NAME.as_bytes())
+        .add(sources)
+        .add(compiler_probes.get(state.compiler)?.banner.as_bytes())
+        .add(
+            ctk_probes
+                .get(state.ctk)
+                .map_or(&[], |p| p.banner.as_bytes()),
         )
-        .unwrap()
-        .progress_chars("##-");
+        .add(state.targets.get(state.cpp).unwrap().as_bytes());
+
+    // The output prefix of --single-graph only moves the build outputs
+    for argument in Configure::arguments(state) {
+        if !argument.starts_with("-DCMAKE_NINJA_OUTPUT_PATH_PREFIX=") {
+            fingerprint.add(argument.as_bytes());
+        }
+    }
+
+    return Some(fingerprint.finish());
+}
+
+fn cell_record(state: &State, outcome: Outcome) -> history::CellRecord {
+    let report = state.report.lock().unwrap();
+    return history::CellRecord {
+        build_type: state.build_type.to_string(),
+        ctk: state.ctk.to_string(),
+        compiler: state.compiler.to_string(),
+        cpp: state.cpp.to_string(),
+        outcome,
+        seconds: report.duration.as_secs_f64(),
+        build_seconds: report.phase_duration(Phase::Build).as_secs_f64(),
+        warnings: report
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Warning)
+            .count(),
+        fingerprint: state.fingerprint.clone(),
+        passed: report
+            .tests
+            .iter()
+            .filter(|t| t.status == TestStatus::Passed)
+            .map(|t| t.name.clone())
+            .collect(),
+        tests: report
+            .tests
+            .iter()
+            .filter(|t| t.status != TestStatus::Passed)
+            .map(|t| history::TestRecord {
+                name: t.name.clone(),
+                status: t.status,
+                detail: t.detail.clone(),
+                seconds: t.duration.as_secs_f64(),
+            })
+            .collect(),
+    };
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    return match Local.timestamp_opt(timestamp, 0).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => timestamp.to_string(),
+    };
+}
+
+// Lists recorded runs, or shows a single one in the summary table.
+fn show_history(matches: &ArgMatches) -> Exit {
+    let state_dir = state_dir(&build_root());
+    let layout = match get_layout(matches) {
+        Ok(layout) => layout,
+        Err(message) => {
+            eprintln!("{}", message.red());
+            return Exit::Usage;
+        }
+    };
+
+    if let Some(id) = matches.get_one::<u64>("run") {
+        match history::find(&state_dir, *id) {
+            Some(run) => show_run(&run, layout.as_ref(), get_format(matches)),
+            None => {
+                eprintln!("{}", format!("No run {} in the history", id).red());
+                return Exit::Usage;
+            }
+        }
+        return Exit::Passed;
+    }
+
+    if get_format(matches) != report::Format::Table {
+        eprintln!("{}", "--format needs a run to show".red());
+        return Exit::Usage;
+    }
+
+    let runs = history::load(&state_dir);
+    let limit = *matches.get_one::<usize>("limit").unwrap();
+
+    let mut table = Table::new();
+    table.add_row(Row::from(
+        [
+            "run", "started", "action", "cells", "passed", "failed", "took", "command",
+        ]
+        .iter()
+        .map(|h| h.yellow().bold())
+        .collect::<Vec<ColoredString>>(),
+    ));
+    for run in runs.iter().rev().take(limit) {
+        let count = |outcomes: &[Outcome]| {
+            run.cells
+                .iter()
+                .filter(|c| outcomes.contains(&c.outcome))
+                .count()
+        };
+        table.add_row(Row::from([
+            run.id.to_string(),
+            format_timestamp(run.timestamp),
+            run.action.clone(),
+            run.cells.len().to_string(),
+            count(&[Outcome::Passed, Outcome::Cached]).to_string(),
+            count(&[Outcome::Failed, Outcome::TimedOut]).to_string(),
+            process::format_duration(Duration::from_secs_f64(run.seconds)),
+            run.command
+                .iter()
+                .skip(1)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" "),
+        ]));
+    }
+    table.printstd();
+    return Exit::Passed;
+}
+
+// Prints what changed between two runs. Fails if anything regressed, a run
+// that is missing is a usage error.
+fn show_diff(matches: &ArgMatches) -> Exit {
+    let state_dir = state_dir(&build_root());
+    let mut runs = Vec::new();
+    for id in ["before", "after"].map(|id| *matches.get_one::<u64>(id).unwrap()) {
+        match history::find(&state_dir, id) {
+            Some(run) => runs.push(run),
+            None => {
+                eprintln!("{}", format!("No run {} in the history", id).red());
+                return Exit::Usage;
+            }
+        }
+    }
+
+    let diff = diff::Diff::new(&runs[0], &runs[1]);
+    diff.print();
+    if !diff.regressed.is_empty() {
+        return Exit::Failed;
+    }
+    return Exit::Passed;
+}
+
+fn show_run(run: &history::Run, layout: Option<&report::Layout>, format: report::Format) {
+    if format != report::Format::Table {
+        let cells: Vec<(CellKey, Option<&history::CellRecord>)> = run
+            .cells
+            .iter()
+            .map(|cell| (cell.key(), Some(cell)))
+            .collect();
+        report::print_records(format, &cells);
+        return;
+    }
+
+    println!(
+        "Run {}: {}",
+        run.id,
+        run.command
+            .iter()
+            .skip(1)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    println!(
+        "Started {}, took {}",
+        format_timestamp(run.timestamp),
+        process::format_duration(Duration::from_secs_f64(run.seconds))
+    );
+    for (name, sha) in &run.sources {
+        println!("  {} {}", name, sha);
+    }
+    if run.toolchains.is_empty() {
+        // Runs recorded before toolchains were probed only have the banners
+        for (label, version) in run.compilers.iter().chain(&run.ctks) {
+            println!("  {}: {}", label, version);
+        }
+    }
+    for (label, probe) in &run.toolchains {
+        println!("  {}: {} {}", label, probe.exact(), probe.path.dimmed());
+    }
+
+    // Axes in the order the cells were run
+    let mut types: Vec<&str> = Vec::new();
+    let mut ctks: Vec<&str> = Vec::new();
+    let mut cpps: Vec<&str> = Vec::new();
+    let mut compilers: Vec<&str> = Vec::new();
+    for cell in &run.cells {
+        for (axis, value) in [
+            (&mut types, &cell.build_type),
+            (&mut ctks, &cell.ctk),
+            (&mut cpps, &cell.cpp),
+            (&mut compilers, &cell.compiler),
+        ] {
+            if !axis.contains(&value.as_str()) {
+                axis.push(value);
+            }
+        }
+    }
+
+    let mut result = BuildResult::new(&types, &ctks, &cpps, &compilers);
+    for cell in &run.cells {
+        result.set(
+            &cell.build_type,
+            &cell.ctk,
+            &cell.cpp,
+            &cell.compiler,
+            cell.outcome,
+        );
+    }
+    print_summary(&types, &ctks, &cpps, &compilers, layout, |t, k, p, c| {
+        result.status(t, k, p, c)
+    });
+
+    for cell in run.cells.iter().filter(|c| !c.tests.is_empty()) {
+        println!("{}", report::cell_label(&cell.key()).red().bold());
+        for test in &cell.tests {
+            println!(
+                "  test {} {} ({:.2}s)",
+                test.name,
+                test.detail.red(),
+                test.seconds
+            );
+        }
+    }
+}
+
+fn cell_dir(build_root: &Path, build_type: &str, ctk: &str, cpp: &str, compiler: &str) -> PathBuf {
+    return build_root
+        .join(ctk)
+        .join(build_type)
+        .join(compiler)
+        .join(cpp);
+}
+
+// Coarse age of a result, e.g. `5m`, `3h` or `2d`.
+fn format_age(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    if seconds >= 86400 {
+        return format!("{}d", seconds / 86400);
+    } else if seconds >= 3600 {
+        return format!("{}h", seconds / 3600);
+    } else if seconds < 60 {
+        return "<1m".to_string();
+    }
+    return format!("{}m", seconds / 60);
+}
 
+// Shows the last known outcome of every selected cell from the run history,
+// with its age and whether it predates the current source checkout.
+fn show_status(config: &AppConfig, matches: &ArgMatches) -> Exit {
+    let layout = match get_layout(matches) {
+        Ok(layout) => layout,
+        Err(message) => {
+            eprintln!("{}", message.red());
+            return Exit::Usage;
+        }
+    };
+    let types = get_build_types(config, matches);
+    let compilers = get_compilers(config, matches);
+    let ctks = get_ctks(config, matches);
+    let cpps = get_dialects(config, matches);
+
+    let build_root = build_root();
+    let runs = history::load(&state_dir(&build_root));
+    let heads: HashMap<&str, Option<String>> = config
+        .src
+        .iter()
+        .map(|(name, path)| (name.as_str(), git::head(path)))
+        .collect();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+
+    // Cleaning or skipping a cell says nothing about its state
+    let last = |build_type: &str, ctk: &str, cpp: &str, compiler: &str| {
+        return runs
+            .iter()
+            .rev()
+            .filter(|run| run.action != Clean::NAME)
+            .find_map(|run| {
+                let cell = run.cells.iter().find(|cell| {
+                    cell.build_type == build_type
+                        && cell.ctk == ctk
+                        && cell.cpp == cpp
+                        && cell.compiler == compiler
+                        && cell.outcome != Outcome::NotRun
+                })?;
+                Some((run, cell))
+            });
+    };
+
+    let format = get_format(matches);
+    if format != report::Format::Table {
+        let mut cells: Vec<(CellKey, Option<&history::CellRecord>)> = Vec::new();
         for build_type in &types {
             for ctk in &ctks {
                 for compiler in &compilers {
                     for cpp in &cpps {
-                        let pb = m.add(ProgressBar::new(cpps.len() as u64));
-                        pb.set_style(sty.clone());
-                        pb.set_position(0);
-
-                        let compiler_label = compiler.replace("/", ".");
-                        pb.set_message(format!(
-                            "{}/{}/{}/cpp.{}",
-                            build_type, ctk, compiler_label, cpp
-                        ));
-
-                        s.spawn(|_| {
-                            let result = Arc::clone(&results);
-
-                            let pb = pb;
-                            let compiler_label = compiler.clone();
-                            let ctk = ctk.clone();
-                            let build_type = build_type.clone();
-                            let cpp = cpp.clone();
-                            let current_dir = env::current_dir().unwrap();
-                            let mut build_dir = current_dir.clone();
-
-                            build_dir.push("build");
-                            build_dir.push(ctk.clone());
-                            build_dir.push(&build_type);
-                            build_dir.push(&compiler_label);
-                            build_dir.push(&cpp);
-
-                            fs::create_dir_all(&build_dir).ok();
-                            let build_dir = build_dir.into_os_string().into_string().unwrap();
-
-                            let state = State {
-                                config,
-                                targets: &targets,
-                                pb: &pb,
-                                build_dir,
-                                build_type,
-                                ctk,
-                                compiler,
-                                cpp,
-                                num_threads_per_build,
-                            };
-
-                            // cmake
-                            if T::do_action(&state) {
-                                let mut r = result.lock().unwrap();
-                                r.success(&build_type, &ctk, &cpp, &compiler_label);
-                            }
-
-                            pb.finish();
-                        });
+                        let record = last(build_type, ctk, cpp, compiler).map(|(_, cell)| cell);
+                        cells.push(((build_type, ctk, cpp, compiler), record));
                     }
                 }
             }
         }
+        report::print_records(format, &cells);
+        return Exit::Passed;
+    }
 
-        m.clear().unwrap();
-    });
+    print_summary(
+        &types,
+        &ctks,
+        &cpps,
+        &compilers,
+        layout.as_ref(),
+        |build_type, ctk, cpp, compiler| {
+            return match last(build_type, ctk, cpp, compiler) {
+                Some((run, cell)) => {
+                    let stale = run.sources.iter().any(|(name, sha)| {
+                        heads
+                            .get(name.as_str())
+                            .is_some_and(|head| head.as_ref().is_some_and(|head| head != sha))
+                    });
+                    let text = format!(
+                        "{} {} {}{}",
+                        cell.outcome.symbol(),
+                        run.action,
+                        format_age(now - run.timestamp),
+                        if stale {
+                            " stale".yellow().to_string()
+                        } else {
+                            String::new()
+                        }
+                    );
+                    (text, cell.outcome.failed())
+                }
+                // Failed configures leave no manifest behind and cells
+                // configured before manifests were written have none either
+                None if cell_dir(&build_root, build_type, ctk, cpp, compiler)
+                    .join("CMakeCache.txt")
+                    .exists() =>
+                {
+                    ("no results".dimmed().to_string(), false)
+                }
+                None => ("never configured".dimmed().to_string(), false),
+            };
+        },
+    );
+    return Exit::Passed;
+}
 
-    let result = results.lock().unwrap();
+// Prints errors, timeouts and failed tests of every cell that did not pass.
+fn print_details<'a>(
+    result: &BuildResult<'a>,
+    types: &[&'a str],
+    ctks: &[&'a str],
+    cpps: &[&'a str],
+    compilers: &[&'a str],
+) {
+    for build_type in types {
+        for ctk in ctks {
+            for compiler in compilers {
+                for cpp in cpps {
+                    let report = match result.get_report(build_type, ctk, cpp, compiler) {
+                        Some(report) => report,
+                        None => continue,
+                    };
+                    let errors: Vec<&Diagnostic> = report
+                        .diagnostics
+                        .iter()
+                        .filter(|d| d.severity == Severity::Error)
+                        .collect();
+
+                    let failed_tests: Vec<&TestCase> = report
+                        .tests
+                        .iter()
+                        .filter(|t| matches!(t.status, TestStatus::Failed | TestStatus::Timeout))
+                        .collect();
+
+                    if errors.is_empty() && report.timeout.is_none() && failed_tests.is_empty() {
+                        continue;
+                    }
+
+                    println!(
+                        "{}",
+                        report::cell_label(&(*build_type, *ctk, *cpp, *compiler))
+                            .red()
+                            .bold()
+                    );
+                    if let Some(timeout) = &report.timeout {
+                        println!(
+                            "  {}",
+                            format!("{} {}", timeout.phase.name(), timeout.expiry).magenta()
+                        );
+                        for line in &timeout.tail {
+                            println!("  | {}", line);
+                        }
+                    }
+                    for diagnostic in errors.iter().take(5) {
+                        println!("  {}", diagnostic);
+                    }
+                    if errors.len() > 5 {
+                        println!("  ... and {} more errors", errors.len() - 5);
+                    }
+                    for test in &failed_tests {
+                        println!(
+                            "  test {} {} ({:.2}s)",
+                            test.name,
+                            test.detail.red(),
+                            test.duration.as_secs_f64()
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Prints the nested build type / CTK / dialect / compiler table with the
+// status of each cell, or a flat table when a layout is given. `status`
+// returns the text of a cell and whether it failed.
+fn print_summary<F>(
+    types: &[&str],
+    ctks: &[&str],
+    cpps: &[&str],
+    compilers: &[&str],
+    layout: Option<&report::Layout>,
+    status: F,
+) where
+    F: Fn(&str, &str, &str, &str) -> (String, bool),
+{
+    if let Some(layout) = layout {
+        let mut cells: Vec<CellKey> = Vec::new();
+        for build_type in types {
+            for ctk in ctks {
+                for cpp in cpps {
+                    for compiler in compilers {
+                        cells.push((build_type, ctk, cpp, compiler));
+                    }
+                }
+            }
+        }
+        report::print_flat(&cells, layout, |(build_type, ctk, cpp, compiler)| {
+            return status(build_type, ctk, cpp, compiler);
+        });
+        return;
+    }
 
     let mut summary_table: Table = Table::new();
 
     let mut build_row: Vec<Table> = Vec::new();
-    for build_type in &types {
+    for build_type in types {
         let mut ctk_row: Vec<Table> = Vec::new();
-        for ctk in &ctks {
+        for ctk in ctks {
             let mut cpp_row: Vec<Table> = Vec::new();
-            for cpp in &cpps {
+            for cpp in cpps {
                 let mut compiler_table: Table = Table::new();
-                for compiler in &compilers {
+                for compiler in compilers {
                     compiler_table.add_row(Row::from([
-                        compiler.clear(),
-                        result.status(build_type, ctk, cpp, compiler),
+                        compiler.to_string(),
+                        status(build_type, ctk, cpp, compiler).0,
                     ]));
                 }
                 cpp_row.push(compiler_table);
@@ -741,41 +2393,596 @@ fn perform<T: Action>(config: &AppConfig, matches: &ArgMatches) {
     summary_table.printstd();
 }
 
-fn main() -> std::io::Result<()> {
-    let maybe_config = AppConfig::new();
-
-    match maybe_config {
-        Ok(config) => {
-            let matches = build_cli(&config).get_matches();
-
-            match matches.subcommand() {
-                Some(("config", build_matches)) => {
-                    perform::<Configure>(&config, &build_matches);
-                }
-                Some(("build", build_matches)) => {
-                    perform::<Build>(&config, &build_matches);
-                }
-                Some(("clean", build_matches)) => {
-                    perform::<Clean>(&config, &build_matches);
+fn perform<T: Action>(config: &AppConfig, matches: &ArgMatches) -> Exit {
+    // Only runs have tools to stop, elsewhere Ctrl-C exits right away
+    cancel::install();
+
+    let build_root = build_root();
+    let state_dir = state_dir(&build_root);
+    let layout = match get_layout(matches) {
+        Ok(layout) => layout,
+        Err(message) => {
+            eprintln!("{}", message.red());
+            return Exit::Usage;
+        }
+    };
+    let rerun = match get_rerun(matches, &state_dir, T::NAME) {
+        Ok(rerun) => rerun,
+        Err(message) => {
+            eprintln!("{}", message.red());
+            return Exit::Usage;
+        }
+    };
+    let types = rerun_axis(
+        matches,
+        "types",
+        rerun.as_ref(),
+        BUILD_TYPES.to_vec(),
+        get_build_types(config, matches),
+        |cell| &cell.0,
+    );
+    let ctks = rerun_axis(
+        matches,
+        "ctks",
+        rerun.as_ref(),
+        config.ctk_labels(),
+        get_ctks(config, matches),
+        |cell| &cell.1,
+    );
+    let compilers = rerun_axis(
+        matches,
+        "compilers",
+        rerun.as_ref(),
+        config.compiler_labels(),
+        get_compilers(config, matches),
+        |cell| &cell.2,
+    );
+    let cpps = rerun_axis(
+        matches,
+        "dialects",
+        rerun.as_ref(),
+        DIALECTS.to_vec(),
+        get_dialects(config, matches),
+        |cell| &cell.3,
+    );
+    let is_selected = |build_type: &str, ctk: &str, compiler: &str, cpp: &str| {
+        return rerun.as_ref().is_none_or(|rerun| {
+            rerun.cells.contains(&(
+                build_type.to_string(),
+                ctk.to_string(),
+                compiler.to_string(),
+                cpp.to_string(),
+            ))
+        });
+    };
+
+    // A rerun keeps the targets of the original run unless new ones are given
+    let mut target_names = get_target_names(matches);
+    if let Some(rerun) = &rerun {
+        if target_names.is_empty() {
+            target_names = rerun.run.targets.clone();
+        }
+    }
+    let targets = get_targets(&cpps, &target_names);
+
+    let single_graph = flag(matches, "single-graph");
+    let timeouts = get_timeouts(config, matches);
+    let keep_going = flag(matches, "keep-going");
+    let reconfigure = flag(matches, "reconfigure");
+    let junit_path = matches
+        .try_get_one::<String>("junit")
+        .ok()
+        .flatten()
+        .map(Path::new);
+    let ctest_junit = junit_path.is_some() && ctest::supports_junit();
+    let report_view = matches
+        .try_get_one::<String>("report")
+        .ok()
+        .flatten()
+        .map_or("cells", String::as_str);
+    let pivot = matches
+        .try_get_one::<String>("pivot")
+        .ok()
+        .flatten()
+        .and_then(|axis| report::Axis::parse(axis));
+    let failures = FailureBudget {
+        max: get_max_failures(matches),
+        failures: AtomicUsize::new(0),
+    };
+
+    let mut num_builds = 0;
+    for build_type in &types {
+        for ctk in &ctks {
+            for compiler in &compilers {
+                for cpp in &cpps {
+                    if is_selected(build_type, ctk, compiler, cpp) {
+                        num_builds += 1;
+                    }
                 }
-                Some(("test", build_matches)) => {
-                    perform::<Test>(&config, &build_matches);
+            }
+        }
+    }
+    if let Some(rerun) = &rerun {
+        eprintln!("Rerunning {} cells of run {}", num_builds, rerun.run.id);
+        if num_builds < rerun.cells.len() {
+            eprintln!(
+                "{}",
+                format!(
+                    "{} cells of run {} are not selected or no longer configured",
+                    rerun.cells.len() - num_builds,
+                    rerun.run.id
+                )
+                .yellow()
+            );
+        }
+    }
+    if num_builds == 0 {
+        eprintln!("No cells selected");
+        return Exit::Passed;
+    }
+
+    let jobs = get_jobs(matches);
+    let num_concurrent_builds = std::cmp::min(jobs, num_builds);
+    let mut num_threads_per_build = jobs / num_concurrent_builds;
+
+    let started = Instant::now();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+    let command: Vec<String> = env::args().collect();
+    let events_path = matches.try_get_one::<String>("events").ok().flatten();
+    // The events replace the summary on stdout
+    let summary = events_path.is_none_or(|path| path != "-");
+    let format = get_format(matches);
+    if !summary && format != report::Format::Table {
+        eprintln!(
+            "{}",
+            format!(
+                "--events - cannot be combined with --format {}, both write to stdout",
+                matches.get_one::<String>("format").unwrap()
+            )
+            .red()
+        );
+        return Exit::Usage;
+    }
+    let events = match events_path {
+        Some(path) => match events::Sink::open(path) {
+            Ok(events) => events,
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    format!("Failed to open event stream {}: {}", path, err).red()
+                );
+                return Exit::Usage;
+            }
+        },
+        None => events::Sink::disabled(),
+    };
+    events.emit(events::Event::RunStarted {
+        schema: events::SCHEMA,
+        action: T::NAME,
+        command: &command,
+        timestamp,
+        cells: num_builds,
+    });
+    let (compiler_probes, ctk_probes) = probe_toolchains(config, &state_dir, &compilers, &ctks);
+
+    // Builds and tests are skipped if they already passed with the same inputs
+    let sources = if T::NAME == Build::NAME || T::NAME == Test::NAME {
+        match fingerprint::sources(&config.src) {
+            Ok(sources) => Some(sources),
+            Err(reason) => {
+                eprintln!("{}", format!("Result cache disabled, {}", reason).dimmed());
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // Only the latest outcome of a fingerprint counts, so a later failure
+    // evicts an earlier pass. Reruns of failed cells always run them.
+    let mut cache: HashMap<String, history::CellRecord> = HashMap::new();
+    if sources.is_some() && rerun.is_none() && !flag(matches, "no-cache") {
+        for run in history::load(&state_dir) {
+            for cell in run.cells {
+                if let Some(fingerprint) = cell.fingerprint.clone() {
+                    if cell.outcome.passed() {
+                        cache.insert(fingerprint, cell);
+                    } else {
+                        cache.remove(&fingerprint);
+                    }
                 }
-                Some(("generate-zsh-completions", _)) => {
-                    generate(
-                        Zsh,
-                        &mut build_cli(&config),
-                        "cccl-composer",
-                        &mut io::stdout(),
-                    );
+            }
+        }
+    }
+
+    let pool = Arc::new(JobServer::new(&build_root, jobs).expect("failed to create jobserver"));
+    let shared_jobs = jobserver::ninja_supports_jobserver();
+
+    let (mem_per_job, mem_budget) = get_memory_limits(config, matches, &state_dir);
+    let mut governor = Governor::new(Arc::clone(&pool), mem_per_job, mem_budget);
+    governor.tick();
+
+    // Without a shared jobserver the governor can only hold back cells, so the
+    // budget caps the static -j of every cell instead
+    if !shared_jobs && mem_budget != u64::MAX {
+        let fit = (mem_budget / mem_per_job.max(1)) as usize / num_concurrent_builds;
+        num_threads_per_build = num_threads_per_build.min(fit.max(1));
+    }
+
+    if shared_jobs {
+        eprintln!(
+            "Build with {} jobs shared by {} builds",
+            pool.jobs(),
+            num_builds
+        );
+    } else {
+        eprintln!("Build with {num_threads_per_build} threads per build and {num_concurrent_builds} concurrent builds");
+    }
+    if mem_budget != u64::MAX && shared_jobs {
+        eprintln!(
+            "Memory budget {} with {} per job",
+            memory::format_size(mem_budget),
+            memory::format_size(mem_per_job)
+        );
+    } else if mem_budget != u64::MAX {
+        eprintln!(
+            "{}",
+            format!(
+                "Memory budget {} with {} per job only caps ninja at -j{} per build, \
+                 ninja before 1.13 cannot share jobs to throttle them while building",
+                memory::format_size(mem_budget),
+                memory::format_size(mem_per_job),
+                num_threads_per_build
+            )
+            .yellow()
+        );
+    }
+
+    let reporter = Arc::new(progress::Reporter::new(
+        get_progress(matches),
+        num_builds,
+        events,
+    ));
+    let stop_governor = Arc::new(AtomicBool::new(false));
+    let governor = {
+        let stop_governor = Arc::clone(&stop_governor);
+        std::thread::spawn(move || {
+            governor.run(&stop_governor);
+            governor
+        })
+    };
+    let heartbeats = {
+        let reporter = Arc::clone(&reporter);
+        let stop_governor = Arc::clone(&stop_governor);
+        std::thread::spawn(move || reporter.run(&stop_governor))
+    };
+
+    let mut states: Vec<State> = Vec::new();
+    for build_type in &types {
+        for ctk in &ctks {
+            for compiler in &compilers {
+                for cpp in &cpps {
+                    if !is_selected(build_type, ctk, compiler, cpp) {
+                        continue;
+                    }
+
+                    let pb = reporter.bar(&report::cell_label(&(build_type, ctk, cpp, compiler)));
+
+                    let build_dir = cell_dir(&build_root, build_type, ctk, cpp, compiler);
+
+                    fs::create_dir_all(&build_dir).ok();
+                    fs::remove_file(build_dir.join(ctest::JUNIT_FILE)).ok();
+
+                    let output_prefix = if single_graph {
+                        Some(graph::output_prefix(&build_root, &build_dir))
+                    } else {
+                        None
+                    };
+
+                    let mut state = State {
+                        config,
+                        targets: &targets,
+                        pb,
+                        build_dir: build_dir.into_os_string().into_string().unwrap(),
+                        build_type,
+                        ctk,
+                        compiler,
+                        cpp,
+                        num_threads_per_build,
+                        jobserver: if shared_jobs { Some(&pool) } else { None },
+                        output_prefix,
+                        timeouts: &timeouts,
+                        keep_going,
+                        reconfigure,
+                        ctest_junit,
+                        failures: &failures,
+                        progress: &reporter,
+                        report: Mutex::new(CellReport::default()),
+                        fingerprint: None,
+                    };
+                    if let Some(sources) = &sources {
+                        state.fingerprint =
+                            cell_fingerprint::<T>(&state, sources, &compiler_probes, &ctk_probes);
+                    }
+                    states.push(state);
                 }
-                _ => unreachable!(), // If all subcommands are defined above, anything else is unreachable
             }
         }
-        _ => {
-            println!("configuration loading error");
+    }
+
+    let cached: Vec<bool> = states
+        .iter()
+        .map(|state| {
+            // A cleaned or deleted cell has to be configured and built again
+            let build_dir = Path::new(&state.build_dir);
+            let configured =
+                manifest::load(build_dir).is_some() && build_dir.join("build.ninja").is_file();
+            configured
+                && state
+                    .fingerprint
+                    .as_ref()
+                    .is_some_and(|fingerprint| cache.contains_key(fingerprint))
+        })
+        .collect();
+    let to_run: Vec<bool> = cached.iter().map(|cached| !cached).collect();
+    for (state, cached) in states.iter().zip(&cached) {
+        reporter.queued(&(state.build_type, state.ctk, state.cpp, state.compiler));
+        if *cached {
+            reporter.cached(&state.label(), &state.pb);
         }
     }
+    reporter.start(to_run.iter().filter(|to_run| **to_run).count());
+
+    let outcomes: Vec<Outcome> = if single_graph {
+        let configured = run_cells(&states, &pool, &to_run, false, Configure::do_action);
+        let configured_cells: Vec<bool> =
+            configured.iter().map(|o| *o == Outcome::Passed).collect();
+        let built = Build::do_graph(&states, &configured_cells, &build_root, &pool, jobs);
+        let built_cells: Vec<bool> = built.iter().map(|o| *o == Outcome::Passed).collect();
+        let after_build = run_cells(&states, &pool, &built_cells, true, T::after_build);
+
+        configured
+            .into_iter()
+            .zip(built)
+            .zip(after_build)
+            .map(|((configured, built), after_build)| {
+                if configured != Outcome::Passed {
+                    configured
+                } else if built != Outcome::Passed {
+                    built
+                } else {
+                    after_build
+                }
+            })
+            .collect()
+    } else {
+        run_cells(&states, &pool, &to_run, true, T::do_action)
+    };
+    let outcomes: Vec<Outcome> = outcomes
+        .into_iter()
+        .zip(&cached)
+        .map(|(outcome, cached)| if *cached { Outcome::Cached } else { outcome })
+        .collect();
+
+    reporter.clear();
+
+    stop_governor.store(true, Ordering::Relaxed);
+    let governor = governor.join().unwrap();
+    heartbeats.join().unwrap();
+    // Configure and clean only run cmake, whose peak says nothing about compile jobs
+    let compiled = states.iter().any(|state| {
+        let report = state.report.lock().unwrap();
+        return report.phase_durations.contains_key(&Phase::Build);
+    });
+    if compiled {
+        memory::save_learned(&state_dir, governor.peak());
+    }
+
+    let mut result = BuildResult::new(&types, &ctks, &cpps, &compilers);
+    let mut suites: Vec<junit::Suite> = Vec::new();
+    let mut cells: Vec<CellKey> = Vec::new();
+    let mut records: Vec<history::CellRecord> = Vec::new();
+    for (state, outcome) in states.into_iter().zip(outcomes) {
+        cells.push((state.build_type, state.ctk, state.cpp, state.compiler));
+        records.push(
+            match state.fingerprint.as_ref().and_then(|f| cache.get(f)) {
+                Some(record) if outcome == Outcome::Cached => history::CellRecord {
+                    outcome,
+                    seconds: 0.0,
+                    ..record.clone()
+                },
+                _ => cell_record(&state, outcome),
+            },
+        );
+        if junit_path.is_some() {
+            suites.push(junit_suite(&state, outcome));
+        }
+
+        result.set(
+            state.build_type,
+            state.ctk,
+            state.cpp,
+            state.compiler,
+            outcome,
+        );
+        if !matches!(outcome, Outcome::NotRun | Outcome::Cached) {
+            let report = state.report.lock().unwrap();
+            reporter.cell(
+                &state.label(),
+                outcome.name(),
+                &cell_details(&state, &report),
+                &report.diagnostics,
+            );
+        }
+        result.report(
+            state.build_type,
+            state.ctk,
+            state.cpp,
+            state.compiler,
+            state.report.into_inner().unwrap(),
+        );
+    }
+
+    let mut run = history::Run {
+        id: 0,
+        timestamp,
+        action: T::NAME.to_string(),
+        command,
+        targets: target_names,
+        seconds: started.elapsed().as_secs_f64(),
+        sources: config
+            .src
+            .iter()
+            .filter_map(|(name, path)| Some((name.clone(), git::head(path)?)))
+            .collect(),
+        compilers: compiler_probes
+            .iter()
+            .map(|(label, probe)| (label.clone(), probe.banner.clone()))
+            .collect(),
+        ctks: ctk_probes
+            .iter()
+            .map(|(label, probe)| (label.clone(), probe.banner.clone()))
+            .collect(),
+        toolchains: compiler_probes.into_iter().chain(ctk_probes).collect(),
+        cells: records,
+    };
+    let recorded = history::append(&state_dir, &mut run);
+
+    let mut outcomes: BTreeMap<&str, usize> = BTreeMap::new();
+    for cell in &run.cells {
+        *outcomes.entry(cell.outcome.name()).or_default() += 1;
+    }
+    reporter.events().emit(events::Event::RunFinished {
+        run: recorded.is_ok().then_some(run.id),
+        seconds: run.seconds,
+        outcomes,
+    });
+
+    match format {
+        _ if !summary => {}
+        report::Format::Table if report_view == "by-test" => {
+            report::print_by_test(&result, &cells, pivot);
+        }
+        report::Format::Table => {
+            print_summary(
+                &types,
+                &ctks,
+                &cpps,
+                &compilers,
+                layout.as_ref(),
+                |t, k, p, c| result.status(t, k, p, c),
+            );
+        }
+        _ => report::print_cells(format, &result, &cells),
+    }
+
+    if let Some(path) = matches.try_get_one::<String>("html").ok().flatten() {
+        if let Err(err) = html::write(Path::new(path), &run, &result, &cells, &build_root) {
+            eprintln!(
+                "{}",
+                format!("Failed to write HTML report {}: {}", path, err).red()
+            );
+        }
+    }
+
+    if let Some(path) = junit_path {
+        if let Err(err) = junit::write(path, &suites) {
+            eprintln!(
+                "{}",
+                format!("Failed to write JUnit report {}: {}", path.display(), err).red()
+            );
+        }
+    }
+
+    match cancel::reason() {
+        Some(cancel::Reason::Signal) => {
+            eprintln!("{}", "Run interrupted, results are partial".yellow().bold());
+        }
+        Some(cancel::Reason::Failures) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Run stopped after {} failed cells, results are partial",
+                    failures.failures.load(Ordering::SeqCst)
+                )
+                .yellow()
+                .bold()
+            );
+        }
+        None => {}
+    }
+
+    if summary && format == report::Format::Table {
+        print_details(&result, &types, &ctks, &cpps, &compilers);
+    }
+
+    match recorded {
+        Ok(()) => eprintln!("{}", format!("Recorded as run {}", run.id).dimmed()),
+        Err(err) => eprintln!("{}", format!("Failed to record run: {}", err).red()),
+    }
+
+    if cancel::reason() == Some(cancel::Reason::Signal) {
+        return Exit::Interrupted;
+    } else if run
+        .cells
+        .iter()
+        .any(|cell| cell.outcome.failed() || cell.outcome == Outcome::Interrupted)
+    {
+        return Exit::Failed;
+    }
+    return Exit::Passed;
+}
+
+fn main() -> ExitCode {
+    // init creates the configuration the other subcommands need
+    if env::args().nth(1).as_deref() == Some("init") {
+        let matches = Command::new("cccl-composer")
+            .subcommand(init::command())
+            .get_matches();
+        let exit = init::run(matches.subcommand_matches("init").unwrap());
+        return ExitCode::from(exit.code());
+    }
+
+    let config = match AppConfig::new() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Failed to load configuration {}: {}",
+                    AppConfig::path().display(),
+                    err
+                )
+                .red()
+            );
+            if !AppConfig::path().exists() {
+                eprintln!("Run `cccl-composer init` to create it.");
+            }
+            return ExitCode::from(Exit::Config.code());
+        }
+    };
+    let matches = build_cli(&config).get_matches();
+
+    let exit = match matches.subcommand() {
+        Some(("config", build_matches)) => perform::<Configure>(&config, build_matches),
+        Some(("build", build_matches)) => perform::<Build>(&config, build_matches),
+        Some(("clean", build_matches)) => perform::<Clean>(&config, build_matches),
+        Some(("test", build_matches)) => perform::<Test>(&config, build_matches),
+        Some(("status", status_matches)) => show_status(&config, status_matches),
+        Some(("history", history_matches)) => show_history(history_matches),
+        Some(("diff", diff_matches)) => show_diff(diff_matches),
+        Some(("generate-zsh-completions", _)) => {
+            generate(
+                Zsh,
+                &mut build_cli(&config),
+                "cccl-composer",
+                &mut io::stdout(),
+            );
+            Exit::Passed
+        }
+        _ => unreachable!(), // If all subcommands are defined above, anything else is unreachable
+    };
 
-    Ok(())
+    return ExitCode::from(exit.code());
 }
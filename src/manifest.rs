@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FILE: &str = "cccl-composer.json";
+
+/// What the composer last did to a cell's build directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Arguments cmake was last run with successfully.
+    #[serde(default)]
+    pub cmake_args: Vec<String>,
+}
+
+pub fn load(build_dir: &Path) -> Option<Manifest> {
+    let content = fs::read_to_string(build_dir.join(FILE)).ok()?;
+    return serde_json::from_str(&content).ok();
+}
+
+pub fn save(build_dir: &Path, manifest: &Manifest) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(manifest)?;
+    return fs::write(build_dir.join(FILE), content);
+}
+
+pub fn remove(build_dir: &Path) {
+    fs::remove_file(build_dir.join(FILE)).ok();
+}
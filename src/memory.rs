@@ -0,0 +1,228 @@
+use crate::jobserver::JobServer;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Per-job estimate used when nothing is configured and no run has been observed.
+pub const DEFAULT_PER_JOB: u64 = 2 * GIB;
+
+const LEARNED_FILE: &str = "peak-rss";
+
+// Share of the learned peak kept by a run that peaked lower, so that a run
+// compiling only light translation units does not reset the estimate.
+const LEARNED_DECAY: f64 = 0.9;
+
+/// Parses sizes like `512M`, `4G` or `4GiB`. A bare number is in bytes.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size `{}`", s))?;
+    let scale: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches("IB") {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => GIB,
+        "T" | "TB" => 1024 * GIB,
+        _ => return Err(format!("invalid size unit in `{}`", s)),
+    };
+
+    return value
+        .checked_mul(scale)
+        .ok_or_else(|| format!("invalid size `{}`", s));
+}
+
+pub fn format_size(bytes: u64) -> String {
+    return format!("{:.1} GiB", bytes as f64 / GIB as f64);
+}
+
+pub struct MemInfo {
+    pub total: u64,
+    pub available: u64,
+}
+
+pub fn meminfo() -> Option<MemInfo> {
+    let content = fs::read_to_string("/proc/meminfo").ok()?;
+    let mut fields: HashMap<&str, u64> = HashMap::new();
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+            if let Ok(kb) = value.parse::<u64>() {
+                fields.insert(key.trim_end_matches(':'), kb * 1024);
+            }
+        }
+    }
+
+    return Some(MemInfo {
+        total: *fields.get("MemTotal")?,
+        available: *fields.get("MemAvailable")?,
+    });
+}
+
+struct Usage {
+    total: u64,
+    peak_process: u64,
+}
+
+// Resident memory of every process descended from the composer.
+fn descendant_usage() -> Usage {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    if let Ok(entries) = fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let pid = match entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<u32>().ok())
+            {
+                Some(pid) => pid,
+                None => continue,
+            };
+            // The command name may contain spaces, the ppid follows its closing paren
+            if let Ok(stat) = fs::read_to_string(entry.path().join("stat")) {
+                if let Some(ppid) = stat
+                    .rsplit_once(')')
+                    .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+                    .and_then(|p| p.parse::<u32>().ok())
+                {
+                    children.entry(ppid).or_default().push(pid);
+                }
+            }
+        }
+    }
+
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+    let mut usage = Usage {
+        total: 0,
+        peak_process: 0,
+    };
+    let mut stack: Vec<u32> = vec![std::process::id()];
+    while let Some(pid) = stack.pop() {
+        if let Some(kids) = children.get(&pid) {
+            for kid in kids {
+                let rss = fs::read_to_string(format!("/proc/{}/statm", kid))
+                    .ok()
+                    .and_then(|statm| statm.split_whitespace().nth(1)?.parse::<u64>().ok())
+                    .unwrap_or(0)
+                    * page_size;
+                usage.total += rss;
+                usage.peak_process = usage.peak_process.max(rss);
+                stack.push(*kid);
+            }
+        }
+    }
+
+    return usage;
+}
+
+/// Returns the per-job estimate learned from the peak RSS of a previous run,
+/// never below `DEFAULT_PER_JOB`.
+pub fn load_learned(state_dir: &Path) -> Option<u64> {
+    let learned: u64 = fs::read_to_string(state_dir.join(LEARNED_FILE))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    return Some(learned.max(DEFAULT_PER_JOB));
+}
+
+/// Learns `peak` as the per-job estimate, only decaying the previous estimate
+/// if it is lower. Should only be fed by runs that compiled.
+///
+/// A run that was interrupted or did little may only have seen ninja itself,
+/// so the estimate never drops below `DEFAULT_PER_JOB`.
+pub fn save_learned(state_dir: &Path, peak: u64) {
+    if peak == 0 {
+        return;
+    }
+    let previous = load_learned(state_dir).unwrap_or(DEFAULT_PER_JOB);
+    let learned = peak
+        .max((previous as f64 * LEARNED_DECAY) as u64)
+        .max(DEFAULT_PER_JOB);
+    fs::create_dir_all(state_dir).ok();
+    fs::write(state_dir.join(LEARNED_FILE), learned.to_string()).ok();
+}
+
+/// Keeps the number of job tokens in circulation within a memory budget.
+///
+/// Every tick the governor estimates how many more jobs fit into the memory
+/// that is still free and withholds pool tokens beyond that, so neither ninja
+/// nor the composer can start new jobs or cells while the budget is exhausted.
+pub struct Governor {
+    pool: Arc<JobServer>,
+    per_job: u64,
+    budget: u64,
+    held: usize,
+    peak: u64,
+}
+
+impl Governor {
+    pub fn new(pool: Arc<JobServer>, per_job: u64, budget: u64) -> Self {
+        return Self {
+            pool,
+            per_job: per_job.max(1),
+            budget,
+            held: 0,
+            peak: 0,
+        };
+    }
+
+    /// Largest resident size of a single child process seen so far.
+    pub fn peak(&self) -> u64 {
+        return self.peak;
+    }
+
+    pub fn tick(&mut self) {
+        let usage = descendant_usage();
+        self.peak = self.peak.max(usage.peak_process);
+
+        let mut free = self.budget.saturating_sub(usage.total);
+        if let Some(info) = meminfo() {
+            free = free.min(info.available);
+        }
+
+        let available = self.pool.available();
+        let in_flight = self.pool.jobs().saturating_sub(available + self.held);
+
+        // Never starve the matrix completely, one job always fits
+        let mut affordable = (free / self.per_job) as usize;
+        if in_flight == 0 {
+            affordable = affordable.max(1);
+        }
+
+        if available > affordable {
+            for _ in affordable..available {
+                if !self.pool.withhold() {
+                    break;
+                }
+                self.held += 1;
+            }
+        } else {
+            let n = std::cmp::min(self.held, affordable - available);
+            for _ in 0..n {
+                self.pool.restore();
+            }
+            self.held -= n;
+        }
+    }
+
+    pub fn run(&mut self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            self.tick();
+            thread::sleep(Duration::from_millis(500));
+        }
+
+        for _ in 0..self.held {
+            self.pool.restore();
+        }
+        self.held = 0;
+    }
+}
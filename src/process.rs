@@ -0,0 +1,252 @@
+use crate::cancel;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long to keep draining output after the process group was killed.
+const KILL_GRACE: Duration = Duration::from_secs(5);
+
+// How long to keep draining output after the child exited, descendants that
+// inherited its stdout or stderr can hold the pipes open indefinitely.
+const EXIT_DRAIN: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Parses durations like `90s`, `30m` or `2h`. A bare number is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", s))?;
+    let scale: u64 = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => return Err(format!("invalid duration unit in `{}`", s)),
+    };
+
+    let secs = value
+        .checked_mul(scale)
+        .ok_or_else(|| format!("invalid duration `{}`", s))?;
+    return Ok(Duration::from_secs(secs));
+}
+
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        return format!("{}h{:02}m", secs / 3600, secs % 3600 / 60);
+    } else if secs >= 60 {
+        return format!("{}m{:02}s", secs / 60, secs % 60);
+    }
+    return format!("{}s", secs);
+}
+
+/// Limits after which a running process is killed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Total wall time.
+    pub timeout: Option<Duration>,
+    /// Time without any output.
+    pub stall: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Expiry {
+    Timeout(Duration),
+    Stalled(Duration),
+}
+
+impl fmt::Display for Expiry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Expiry::Timeout(d) => write!(f, "timed out after {}", format_duration(*d)),
+            Expiry::Stalled(d) => write!(f, "output stalled for {}", format_duration(*d)),
+        };
+    }
+}
+
+pub struct Finished {
+    pub status: ExitStatus,
+    /// Set when the process was killed for exceeding one of its limits.
+    pub expired: Option<Expiry>,
+}
+
+impl Finished {
+    pub fn success(&self) -> bool {
+        return self.expired.is_none() && self.status.success();
+    }
+}
+
+enum Message {
+    Line(Stream, String),
+    Exited(io::Result<ExitStatus>),
+}
+
+// Reads `reader` line by line on its own thread and forwards every line to `tx`.
+// The sender is dropped once the stream reaches EOF, which is how the consumer
+// learns that the child closed its end of the pipe.
+fn forward<R: Read + Send + 'static>(
+    reader: R,
+    stream: Stream,
+    tx: mpsc::Sender<Message>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buf: Vec<u8> = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf)
+                        .trim_end_matches(['\n', '\r'])
+                        .to_string();
+                    if tx.send(Message::Line(stream, line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Runs `cmd` to completion, streaming stdout and stderr independently.
+///
+/// Every line is appended to the log file at `log_path` and handed to
+/// `on_line` in the order it was received. The exit status is collected on a
+/// thread of its own, once the child exited its output is only read until both
+/// streams are closed or for a short while longer.
+///
+/// The child leads its own process group, which is signalled as a whole when
+/// the run is cancelled or killed as a whole when it exceeds `limits`.
+pub fn run<F>(
+    cmd: &mut Command,
+    log_path: &Path,
+    limits: &Limits,
+    mut on_line: F,
+) -> io::Result<Finished>
+where
+    F: FnMut(Stream, &str),
+{
+    let mut log = File::create(log_path)?;
+    writeln!(log, "$ {:?}", cmd)?;
+
+    let mut child = match cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            writeln!(log, "# failed to spawn: {}", e).ok();
+            return Err(e);
+        }
+    };
+
+    let pgid = child.id() as i32;
+    cancel::register(pgid);
+
+    let (tx, rx) = mpsc::channel();
+    let readers = [
+        forward(child.stdout.take().unwrap(), Stream::Stdout, tx.clone()),
+        forward(child.stderr.take().unwrap(), Stream::Stderr, tx.clone()),
+    ];
+    thread::spawn(move || {
+        tx.send(Message::Exited(child.wait())).ok();
+    });
+
+    let started = Instant::now();
+    let mut last_output = started;
+    let mut expired: Option<Expiry> = None;
+    let mut killed_at = started;
+    let mut status: Option<io::Result<ExitStatus>> = None;
+    let mut exited_at = started;
+
+    loop {
+        let deadline = match (&status, expired) {
+            (Some(_), _) => Some((exited_at + EXIT_DRAIN, None)),
+            (None, Some(_)) => Some((killed_at + KILL_GRACE, None)),
+            (None, None) => [
+                limits
+                    .timeout
+                    .map(|t| (started + t, Some(Expiry::Timeout(t)))),
+                limits
+                    .stall
+                    .map(|t| (last_output + t, Some(Expiry::Stalled(t)))),
+            ]
+            .into_iter()
+            .flatten()
+            .min_by_key(|(at, _)| *at),
+        };
+
+        let received = match deadline {
+            Some((at, _)) => rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(Message::Line(stream, line)) => {
+                last_output = Instant::now();
+                writeln!(log, "{}", line).ok();
+                on_line(stream, &line);
+            }
+            Ok(Message::Exited(exited)) => {
+                status = Some(exited);
+                exited_at = Instant::now();
+            }
+            Err(RecvTimeoutError::Timeout) => match deadline {
+                Some((_, Some(expiry))) => {
+                    writeln!(log, "# {}, killing process group", expiry).ok();
+                    unsafe {
+                        libc::kill(-pgid, libc::SIGKILL);
+                    }
+                    expired = Some(expiry);
+                    killed_at = Instant::now();
+                }
+                // Something outside the process group, or a descendant of the
+                // exited child, still holds the pipes
+                _ => break,
+            },
+            Err(RecvTimeoutError::Disconnected) => {
+                for reader in readers {
+                    reader.join().ok();
+                }
+                break;
+            }
+        }
+    }
+
+    // Only reached without a status if the killed child has yet to be reaped
+    let status = match status {
+        Some(status) => status,
+        None => loop {
+            match rx.recv() {
+                Ok(Message::Exited(status)) => break status,
+                Ok(Message::Line(..)) => {}
+                Err(_) => break Err(io::Error::other("lost the exit status of the child")),
+            }
+        },
+    };
+    cancel::unregister(pgid);
+
+    let status = status?;
+    writeln!(log, "# {}", status).ok();
+    Ok(Finished { status, expired })
+}
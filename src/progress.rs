@@ -0,0 +1,418 @@
+use crate::ctest::TestCase;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::events::{self, Event};
+use crate::process::format_duration;
+use crate::report::cell_label;
+use crate::{CellKey, Outcome};
+use chrono::Local;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval between the lines reporting that a long phase is still running.
+const HEARTBEAT: Duration = Duration::from_secs(60);
+
+/// How the progress of a run is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `Bars` if every cell fits on the terminal, `Compact` if not, and
+    /// `Plain` when stderr is not a terminal.
+    Auto,
+    /// A redrawn progress bar per cell, for terminals.
+    Bars,
+    /// An overall progress bar, bars for the running cells and a scrolling
+    /// list of finished cells.
+    Compact,
+    /// One timestamped line per phase transition, plus heartbeats.
+    Plain,
+    /// Plain lines, plus a log group per cell and annotations for
+    /// diagnostics in GitHub Actions.
+    Github,
+    None,
+}
+
+impl Mode {
+    pub const NAMES: [&'static str; 6] = ["auto", "bars", "compact", "plain", "github", "none"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        return match name {
+            "auto" => Some(Mode::Auto),
+            "bars" => Some(Mode::Bars),
+            "compact" => Some(Mode::Compact),
+            "plain" => Some(Mode::Plain),
+            "github" => Some(Mode::Github),
+            "none" => Some(Mode::None),
+            _ => None,
+        };
+    }
+}
+
+struct Running {
+    started: Instant,
+    beat: Instant,
+    pb: ProgressBar,
+}
+
+#[derive(Default)]
+struct Counts {
+    passed: usize,
+    failed: usize,
+    running: usize,
+    queued: usize,
+}
+
+/// Shows the progress of the cells of a run, as progress bars or as lines of
+/// text, and sends it to the event stream.
+pub struct Reporter {
+    mode: Mode,
+    events: events::Sink,
+    multi: MultiProgress,
+    style: ProgressStyle,
+    // Overall progress of the run in compact mode
+    overall: ProgressBar,
+    counts: Mutex<Counts>,
+    // Cells that started their first phase and did not finish yet
+    active: Mutex<HashSet<String>>,
+    running: Mutex<HashMap<(String, &'static str), Running>>,
+    // Diagnostics already annotated, each is only reported once
+    annotated: Mutex<HashSet<String>>,
+}
+
+// Height of the terminal stderr is attached to.
+fn terminal_rows() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+    return Some(size.ws_row as usize).filter(|rows| *rows > 0);
+}
+
+fn timestamp() -> String {
+    return Local::now().format("%H:%M:%S").to_string();
+}
+
+// Data and properties of GitHub workflow commands have their own escaping.
+fn escape_data(s: &str) -> String {
+    return s
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+}
+
+fn escape_property(s: &str) -> String {
+    return escape_data(s).replace(':', "%3A").replace(',', "%2C");
+}
+
+impl Reporter {
+    /// Creates the reporter of a run over `cells` cells.
+    pub fn new(mode: Mode, cells: usize, events: events::Sink) -> Self {
+        let mode = match mode {
+            Mode::Auto if !std::io::stderr().is_terminal() => Mode::Plain,
+            // Leave room for the lines printed before the bars
+            Mode::Auto if terminal_rows().is_some_and(|rows| cells + 4 > rows) => Mode::Compact,
+            Mode::Auto => Mode::Bars,
+            mode => mode,
+        };
+        let target = match mode {
+            Mode::Bars | Mode::Compact => ProgressDrawTarget::stderr(),
+            _ => ProgressDrawTarget::hidden(),
+        };
+
+        return Self {
+            mode,
+            events,
+            multi: MultiProgress::with_draw_target(target),
+            style: ProgressStyle::with_template(
+                "[{elapsed_precise}] {prefix:>9} {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+            )
+            .unwrap()
+            .progress_chars("##-"),
+            overall: ProgressBar::hidden(),
+            counts: Mutex::new(Counts::default()),
+            active: Mutex::new(HashSet::new()),
+            running: Mutex::new(HashMap::new()),
+            annotated: Mutex::new(HashSet::new()),
+        };
+    }
+
+    pub fn events(&self) -> &events::Sink {
+        return &self.events;
+    }
+
+    fn lines(&self) -> bool {
+        return matches!(self.mode, Mode::Plain | Mode::Github);
+    }
+
+    /// Creates the progress bar of a cell. Compact mode only shows it while
+    /// the cell is running.
+    pub fn bar(&self, cell: &str) -> ProgressBar {
+        let pb = ProgressBar::hidden();
+        pb.set_style(self.style.clone());
+        pb.set_message(cell.to_string());
+        if self.mode == Mode::Bars {
+            return self.multi.add(pb);
+        }
+        return pb;
+    }
+
+    /// Starts the overall progress bar once it is known which of the cells
+    /// have to run.
+    pub fn start(&self, cells: usize) {
+        self.counts.lock().unwrap().queued = cells;
+        if self.mode == Mode::Compact {
+            let overall = self.multi.add(self.overall.clone());
+            overall.set_style(self.style.clone());
+            overall.set_prefix("cells");
+            overall.set_length(cells as u64);
+            overall.enable_steady_tick(Duration::from_secs(1));
+            self.update_overall(&self.counts.lock().unwrap());
+        }
+    }
+
+    fn update_overall(&self, counts: &Counts) {
+        self.overall.set_message(format!(
+            "{} passed, {} failed, {} running, {} queued",
+            counts.passed, counts.failed, counts.running, counts.queued
+        ));
+    }
+
+    pub fn queued(&self, key: &CellKey) {
+        let (build_type, ctk, cpp, compiler) = *key;
+        self.events.emit(Event::CellQueued {
+            cell: &cell_label(key),
+            build_type,
+            ctk,
+            compiler,
+            cpp,
+        });
+    }
+
+    /// Lists a cell skipped because an identical one already passed.
+    pub fn cached(&self, cell: &str, pb: &ProgressBar) {
+        self.events.emit(Event::CellFinished {
+            cell,
+            outcome: Outcome::Cached,
+            seconds: 0.0,
+        });
+        match self.mode {
+            Mode::Bars => pb.finish_with_message(format!("{} cached", cell)),
+            Mode::Compact => {
+                self.multi
+                    .println(format!("{} {}", Outcome::Cached.symbol(), cell))
+                    .ok();
+            }
+            Mode::Plain | Mode::Github => eprintln!("[{}] {} cached", timestamp(), cell),
+            Mode::Auto | Mode::None => {}
+        }
+    }
+
+    pub fn started(&self, cell: &str, phase: &'static str, pb: &ProgressBar) {
+        pb.set_prefix(phase);
+        if self.active.lock().unwrap().insert(cell.to_string()) {
+            self.events.emit(Event::CellStarted { cell });
+            if self.mode == Mode::Compact {
+                self.multi.insert_before(&self.overall, pb.clone());
+            }
+            let mut counts = self.counts.lock().unwrap();
+            counts.queued = counts.queued.saturating_sub(1);
+            counts.running += 1;
+            self.update_overall(&counts);
+        }
+        self.events.emit(Event::PhaseStarted { cell, phase });
+
+        let now = Instant::now();
+        self.running.lock().unwrap().insert(
+            (cell.to_string(), phase),
+            Running {
+                started: now,
+                beat: now,
+                pb: pb.clone(),
+            },
+        );
+        if self.lines() {
+            eprintln!("[{}] {} {} started", timestamp(), cell, phase);
+        }
+    }
+
+    pub fn finished(&self, cell: &str, phase: &'static str, succeeded: bool) {
+        let running = self
+            .running
+            .lock()
+            .unwrap()
+            .remove(&(cell.to_string(), phase));
+        let elapsed = running.map_or(Duration::ZERO, |r| r.started.elapsed());
+        self.events.emit(Event::PhaseFinished {
+            cell,
+            phase,
+            succeeded,
+            seconds: elapsed.as_secs_f64(),
+        });
+        if !self.lines() {
+            return;
+        }
+        eprintln!(
+            "[{}] {} {} {} after {}",
+            timestamp(),
+            cell,
+            phase,
+            if succeeded { "passed" } else { "failed" },
+            format_duration(elapsed)
+        );
+    }
+
+    pub fn progress(&self, cell: &str, phase: &'static str, current: u64, total: u64) {
+        self.events.emit(Event::Progress {
+            cell,
+            phase,
+            current,
+            total,
+        });
+    }
+
+    pub fn diagnostic(&self, cell: &str, diagnostic: &Diagnostic) {
+        self.events.emit(Event::Diagnostic {
+            cell,
+            severity: diagnostic.severity,
+            file: &diagnostic.file,
+            line: diagnostic.line,
+            message: &diagnostic.message,
+        });
+    }
+
+    pub fn test(&self, cell: &str, test: &TestCase) {
+        self.events.emit(Event::TestResult {
+            cell,
+            name: &test.name,
+            status: test.status,
+            detail: &test.detail,
+            seconds: test.duration.as_secs_f64(),
+        });
+    }
+
+    /// Records the outcome of a cell whose pipeline is over.
+    pub fn finished_cell(&self, cell: &str, pb: &ProgressBar, outcome: Outcome, elapsed: Duration) {
+        self.events.emit(Event::CellFinished {
+            cell,
+            outcome,
+            seconds: elapsed.as_secs_f64(),
+        });
+        let started = self.active.lock().unwrap().remove(cell);
+        {
+            let mut counts = self.counts.lock().unwrap();
+            if outcome.passed() {
+                counts.passed += 1;
+            } else if outcome.failed() {
+                counts.failed += 1;
+            }
+            if started {
+                counts.running = counts.running.saturating_sub(1);
+            } else {
+                counts.queued = counts.queued.saturating_sub(1);
+            }
+            self.update_overall(&counts);
+        }
+
+        match self.mode {
+            Mode::Bars => pb.finish(),
+            Mode::Compact => {
+                if started {
+                    self.multi.remove(pb);
+                }
+                self.overall.inc(1);
+                self.multi
+                    .println(format!(
+                        "{} {} {}",
+                        outcome.symbol(),
+                        cell,
+                        format_duration(elapsed)
+                    ))
+                    .ok();
+            }
+            Mode::Plain | Mode::Github => {
+                eprintln!("[{}] {} {}", timestamp(), cell, outcome.name());
+            }
+            Mode::Auto | Mode::None => {}
+        }
+    }
+
+    /// Removes all progress bars.
+    pub fn clear(&self) {
+        self.multi.clear().unwrap();
+    }
+
+    // Prints a line for every phase that ran a whole heartbeat interval
+    // without a transition.
+    fn beat(&self) {
+        let mut running = self.running.lock().unwrap();
+        let mut beats: Vec<String> = Vec::new();
+        for ((cell, phase), running) in running.iter_mut() {
+            if running.beat.elapsed() < HEARTBEAT {
+                continue;
+            }
+            running.beat = Instant::now();
+            let mut line = format!(
+                "[{}] {} {} still running after {}",
+                timestamp(),
+                cell,
+                phase,
+                format_duration(running.started.elapsed())
+            );
+            if let Some(length) = running.pb.length().filter(|length| *length > 0) {
+                line.push_str(&format!(" ({}/{})", running.pb.position(), length));
+            }
+            beats.push(line);
+        }
+        beats.sort();
+        for line in beats {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Prints heartbeats until `stop` is set.
+    pub fn run(&self, stop: &AtomicBool) {
+        if !self.lines() {
+            return;
+        }
+        while !stop.load(Ordering::Relaxed) {
+            self.beat();
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// Prints the collapsed details of a finished cell in GitHub Actions,
+    /// and annotates its diagnostics.
+    pub fn cell(&self, cell: &str, outcome: &str, details: &[String], diagnostics: &[Diagnostic]) {
+        if self.mode != Mode::Github {
+            return;
+        }
+
+        eprintln!("::group::{} {}", cell, outcome);
+        for line in details {
+            eprintln!("{}", line);
+        }
+        eprintln!("::endgroup::");
+
+        let mut annotated = self.annotated.lock().unwrap();
+        for diagnostic in diagnostics {
+            if !annotated.insert(diagnostic.to_string()) {
+                continue;
+            }
+            let command = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            eprintln!(
+                "::{} file={},line={},title={}::{}",
+                command,
+                escape_property(&diagnostic.file),
+                diagnostic.line,
+                escape_property(cell),
+                escape_data(&diagnostic.message)
+            );
+        }
+    }
+}
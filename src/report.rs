@@ -0,0 +1,558 @@
+use crate::ctest::TestStatus;
+use crate::diagnostics::Severity;
+use crate::history::CellRecord;
+use crate::{BuildResult, CellKey, Outcome, Phase};
+use colored::*;
+use prettytable::{Row, Table};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// A coordinate of the matrix that cells can be grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Type,
+    Ctk,
+    Compiler,
+    Dialect,
+}
+
+impl Axis {
+    pub const NAMES: [&'static str; 4] = ["type", "ctk", "compiler", "dialect"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        return match name {
+            "type" => Some(Axis::Type),
+            "ctk" => Some(Axis::Ctk),
+            "compiler" => Some(Axis::Compiler),
+            "dialect" | "cpp" => Some(Axis::Dialect),
+            _ => None,
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return Self::NAMES[*self as usize];
+    }
+
+    // Dialects are bare numbers, prefix them so that they stand out in tables.
+    fn display(&self, value: &str) -> String {
+        return match self {
+            Axis::Dialect if !value.is_empty() => format!("cpp.{}", value),
+            _ => value.to_string(),
+        };
+    }
+
+    pub fn value<'a>(&self, cell: &CellKey<'a>) -> &'a str {
+        let (build_type, ctk, cpp, compiler) = *cell;
+        return match self {
+            Axis::Type => build_type,
+            Axis::Ctk => ctk,
+            Axis::Compiler => compiler,
+            Axis::Dialect => cpp,
+        };
+    }
+}
+
+pub fn cell_label(cell: &CellKey) -> String {
+    let (build_type, ctk, cpp, compiler) = *cell;
+    return format!("{}/{}/{}/cpp.{}", build_type, ctk, compiler, cpp);
+}
+
+/// How `print_flat` arranges the cells: the values of the `rows` axes make
+/// up the rows and those of the `cols` axes the columns.
+pub struct Layout {
+    pub rows: Vec<Axis>,
+    pub cols: Vec<Axis>,
+    /// Hide rows and columns without a failing cell.
+    pub collapse: bool,
+    /// Only print the status of failing cells, implies `collapse`.
+    pub failing_only: bool,
+}
+
+// Prints the values of one axis per header line or row column, leaving out
+// values repeated from the previous line of the same group.
+fn group_labels<'a>(keys: &[&Vec<&'a str>]) -> Vec<Vec<&'a str>> {
+    let mut labels = Vec::new();
+    let mut previous: Option<&Vec<&str>> = None;
+    for key in keys {
+        let mut label = Vec::new();
+        let mut same = true;
+        for (i, value) in key.iter().enumerate() {
+            same = same && previous.is_some_and(|p| p[i] == *value);
+            label.push(if same { "" } else { *value });
+        }
+        labels.push(label);
+        previous = Some(key);
+    }
+    return labels;
+}
+
+fn axis_key<'a>(axes: &[Axis], cell: &CellKey<'a>) -> Vec<&'a str> {
+    return axes.iter().map(|axis| axis.value(cell)).collect();
+}
+
+/// Prints a single table of `cells` pivoted according to `layout`. `status`
+/// returns the text of a cell and whether it failed.
+pub fn print_flat<F>(cells: &[CellKey], layout: &Layout, status: F)
+where
+    F: Fn(&CellKey) -> (String, bool),
+{
+    let mut rows: Vec<Vec<&str>> = Vec::new();
+    let mut cols: Vec<Vec<&str>> = Vec::new();
+    let mut grid: HashMap<(usize, usize), (String, bool)> = HashMap::new();
+    for cell in cells {
+        let (row_key, col_key) = (axis_key(&layout.rows, cell), axis_key(&layout.cols, cell));
+        let row = rows.iter().position(|r| *r == row_key).unwrap_or_else(|| {
+            rows.push(row_key);
+            rows.len() - 1
+        });
+        let col = cols.iter().position(|c| *c == col_key).unwrap_or_else(|| {
+            cols.push(col_key);
+            cols.len() - 1
+        });
+        grid.insert((row, col), status(cell));
+    }
+
+    let collapse = layout.collapse || layout.failing_only;
+    let failing = |row: Option<usize>, col: Option<usize>| {
+        return grid.iter().any(|((r, c), (_, failed))| {
+            *failed && row.is_none_or(|row| row == *r) && col.is_none_or(|col| col == *c)
+        });
+    };
+    let visible_rows: Vec<usize> = (0..rows.len())
+        .filter(|row| !collapse || failing(Some(*row), None))
+        .collect();
+    let visible_cols: Vec<usize> = (0..cols.len())
+        .filter(|col| !collapse || failing(None, Some(*col)))
+        .collect();
+
+    if visible_rows.is_empty() || visible_cols.is_empty() {
+        println!(
+            "{}",
+            format!("No failing cells out of {}", cells.len()).green()
+        );
+        return;
+    }
+
+    let mut table = Table::new();
+    let col_labels = group_labels(&visible_cols.iter().map(|c| &cols[*c]).collect::<Vec<_>>());
+    for (level, axis) in layout.cols.iter().enumerate() {
+        let mut header: Vec<ColoredString> = Vec::new();
+        for row_axis in &layout.rows {
+            header.push(if level + 1 == layout.cols.len() {
+                row_axis.name().yellow().bold()
+            } else {
+                "".normal()
+            });
+        }
+        header.extend(
+            col_labels
+                .iter()
+                .map(|label| axis.display(label[level]).yellow().bold()),
+        );
+        table.add_row(Row::from(header));
+    }
+
+    let row_labels = group_labels(&visible_rows.iter().map(|r| &rows[*r]).collect::<Vec<_>>());
+    for (row, labels) in visible_rows.iter().zip(&row_labels) {
+        let mut line: Vec<String> = layout
+            .rows
+            .iter()
+            .zip(labels)
+            .map(|(axis, label)| axis.display(label))
+            .collect();
+        for col in &visible_cols {
+            line.push(match grid.get(&(*row, *col)) {
+                Some((_, false)) if layout.failing_only => String::new(),
+                Some((text, _)) => text.clone(),
+                None => String::new(),
+            });
+        }
+        table.add_row(Row::from(line));
+    }
+    table.printstd();
+
+    let hidden_rows = rows.len() - visible_rows.len();
+    let hidden_cols = cols.len() - visible_cols.len();
+    if hidden_rows > 0 || hidden_cols > 0 {
+        println!(
+            "{}",
+            format!(
+                "{} rows and {} columns without failures hidden",
+                hidden_rows, hidden_cols
+            )
+            .dimmed()
+        );
+    }
+}
+
+/// What happened to one test in one cell, ordered from best to worst so that
+/// merged cells show the worst result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TestResult {
+    Passed,
+    NotRun,
+    NotBuilt,
+    TimedOut,
+    Failed,
+}
+
+impl TestResult {
+    fn symbol(&self) -> ColoredString {
+        return match self {
+            TestResult::Passed => "✓".green(),
+            TestResult::Failed => "✗".red(),
+            TestResult::TimedOut => "T".magenta().bold(),
+            TestResult::NotBuilt => "B".yellow(),
+            TestResult::NotRun => "-".dimmed(),
+        };
+    }
+}
+
+// CUB test names carry their dialect, e.g. `cub.cpp14.test.device_scan`, and
+// only exist in cells building that dialect.
+fn belongs(test: &str, cpp: &str) -> bool {
+    return !test.contains(".cpp") || test.contains(&format!(".cpp{}.", cpp));
+}
+
+/// Prints a table with a row per test and a column per cell, or per value of
+/// `pivot` when given.
+pub fn print_by_test(result: &BuildResult, cells: &[CellKey], pivot: Option<Axis>) {
+    let mut names: BTreeSet<&str> = BTreeSet::new();
+    let mut statuses: Vec<HashMap<&str, TestStatus>> = Vec::new();
+    for cell in cells {
+        let mut status = HashMap::new();
+        if let Some(report) = result.reports.get(cell) {
+            for test in &report.tests {
+                names.insert(&test.name);
+                status.insert(test.name.as_str(), test.status);
+            }
+        }
+        statuses.push(status);
+    }
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut column_of: Vec<usize> = Vec::new();
+    for cell in cells {
+        let label = match pivot {
+            Some(axis) => axis.value(cell).to_string(),
+            None => cell_label(cell),
+        };
+        let column = match columns.iter().position(|c| *c == label) {
+            Some(column) => column,
+            None => {
+                columns.push(label);
+                columns.len() - 1
+            }
+        };
+        column_of.push(column);
+    }
+
+    let mut table = Table::new();
+    let mut header = vec!["test".yellow().bold()];
+    header.extend(columns.iter().map(|c| c.yellow().bold()));
+    table.add_row(Row::from(header));
+
+    for name in &names {
+        let mut row: Vec<Option<TestResult>> = vec![None; columns.len()];
+        for (i, cell) in cells.iter().enumerate() {
+            let (_, _, cpp, _) = *cell;
+            let test_result = match statuses[i].get(name) {
+                Some(TestStatus::Passed) => TestResult::Passed,
+                Some(TestStatus::Failed) => TestResult::Failed,
+                Some(TestStatus::Timeout) => TestResult::TimedOut,
+                Some(TestStatus::NotRun) => TestResult::NotRun,
+                None if !belongs(name, cpp) => continue,
+                None => match result.reports.get(cell).and_then(|r| r.failed_phase) {
+                    Some(Phase::Configure) | Some(Phase::Build) => TestResult::NotBuilt,
+                    _ => TestResult::NotRun,
+                },
+            };
+            let merged = &mut row[column_of[i]];
+            *merged = std::cmp::max(*merged, Some(test_result));
+        }
+
+        let mut cells_row = vec![name.normal()];
+        cells_row.extend(row.iter().map(|r| r.map_or("".normal(), |r| r.symbol())));
+        table.add_row(Row::from(cells_row));
+    }
+
+    table.printstd();
+    println!(
+        "{} passed  {} failed  {} timed out  {} not built  {} not run",
+        TestResult::Passed.symbol(),
+        TestResult::Failed.symbol(),
+        TestResult::TimedOut.symbol(),
+        TestResult::NotBuilt.symbol(),
+        TestResult::NotRun.symbol()
+    );
+}
+
+/// How the summary of a matrix run is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 4] = ["table", "json", "csv", "markdown"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        return match name {
+            "table" => Some(Format::Table),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "markdown" => Some(Format::Markdown),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Serialize)]
+struct FailedTest<'a> {
+    name: &'a str,
+    status: TestStatus,
+    detail: &'a str,
+    seconds: f64,
+}
+
+/// A cell as printed by `--format json`.
+#[derive(Serialize)]
+struct CellOutput<'a> {
+    build_type: &'a str,
+    ctk: &'a str,
+    compiler: &'a str,
+    cpp: &'a str,
+    outcome: Outcome,
+    seconds: f64,
+    failed_phase: Option<&'static str>,
+    timeout: Option<String>,
+    errors: Vec<String>,
+    warnings: usize,
+    passed_tests: usize,
+    failed_tests: Vec<FailedTest<'a>>,
+}
+
+fn cell_outputs<'a>(result: &'a BuildResult, cells: &[CellKey<'a>]) -> Vec<CellOutput<'a>> {
+    let mut outputs = Vec::new();
+    for cell in cells {
+        let (build_type, ctk, cpp, compiler) = *cell;
+        let mut output = CellOutput {
+            build_type,
+            ctk,
+            compiler,
+            cpp,
+            outcome: result.outcome(build_type, ctk, cpp, compiler),
+            seconds: 0.0,
+            failed_phase: None,
+            timeout: None,
+            errors: Vec::new(),
+            warnings: 0,
+            passed_tests: 0,
+            failed_tests: Vec::new(),
+        };
+
+        if let Some(report) = result.reports.get(cell) {
+            output.seconds = report.duration.as_secs_f64();
+            output.failed_phase = report.failed_phase.map(|phase| phase.name());
+            output.timeout = report.timeout.as_ref().map(|t| t.expiry.to_string());
+            for diagnostic in &report.diagnostics {
+                match diagnostic.severity {
+                    Severity::Error => output.errors.push(diagnostic.to_string()),
+                    Severity::Warning => output.warnings += 1,
+                }
+            }
+            for test in &report.tests {
+                match test.status {
+                    TestStatus::Passed => output.passed_tests += 1,
+                    TestStatus::NotRun => {}
+                    TestStatus::Failed | TestStatus::Timeout => {
+                        output.failed_tests.push(FailedTest {
+                            name: &test.name,
+                            status: test.status,
+                            detail: &test.detail,
+                            seconds: test.duration.as_secs_f64(),
+                        })
+                    }
+                }
+            }
+        }
+
+        outputs.push(output);
+    }
+    return outputs;
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
+    }
+    return field.to_string();
+}
+
+fn markdown_outcome(outcome: Outcome) -> &'static str {
+    return match outcome {
+        Outcome::Passed => "✅",
+        Outcome::Cached => "✅ cached",
+        Outcome::Failed => "❌",
+        Outcome::TimedOut => "⏱️ timeout",
+        Outcome::Interrupted => "⚠️ interrupted",
+        Outcome::NotRun => "➖",
+    };
+}
+
+// Cells recorded in the history, which keeps neither the diagnostics nor the
+// failed phase. Cells without a record were not run.
+fn record_outputs<'a>(cells: &[(CellKey<'a>, Option<&'a CellRecord>)]) -> Vec<CellOutput<'a>> {
+    let mut outputs = Vec::new();
+    for ((build_type, ctk, cpp, compiler), record) in cells {
+        let mut output = CellOutput {
+            build_type,
+            ctk,
+            compiler,
+            cpp,
+            outcome: Outcome::NotRun,
+            seconds: 0.0,
+            failed_phase: None,
+            timeout: None,
+            errors: Vec::new(),
+            warnings: 0,
+            passed_tests: 0,
+            failed_tests: Vec::new(),
+        };
+
+        if let Some(record) = record {
+            output.outcome = record.outcome;
+            output.seconds = record.seconds;
+            output.warnings = record.warnings;
+            output.passed_tests = record.passed.len();
+            for test in &record.tests {
+                if matches!(test.status, TestStatus::Failed | TestStatus::Timeout) {
+                    output.failed_tests.push(FailedTest {
+                        name: &test.name,
+                        status: test.status,
+                        detail: &test.detail,
+                        seconds: test.seconds,
+                    });
+                }
+            }
+        }
+
+        outputs.push(output);
+    }
+    return outputs;
+}
+
+/// Prints the cells of a run in a machine-readable or Markdown format.
+pub fn print_cells(format: Format, result: &BuildResult, cells: &[CellKey]) {
+    print_outputs(format, &cell_outputs(result, cells));
+}
+
+/// Prints recorded cells like `print_cells`, e.g. for `status` or `history`.
+pub fn print_records(format: Format, cells: &[(CellKey, Option<&CellRecord>)]) {
+    print_outputs(format, &record_outputs(cells));
+}
+
+fn print_outputs(format: Format, outputs: &[CellOutput]) {
+    match format {
+        Format::Table => {}
+        Format::Json => {
+            println!("{}", serde_json::to_string_pretty(&outputs).unwrap());
+        }
+        Format::Csv => {
+            println!("build_type,ctk,compiler,cpp,outcome,seconds,failed_phase,errors,warnings,passed_tests,failed_tests");
+            for output in outputs {
+                let failed_tests: Vec<&str> = output.failed_tests.iter().map(|t| t.name).collect();
+                let fields = [
+                    output.build_type.to_string(),
+                    output.ctk.to_string(),
+                    output.compiler.to_string(),
+                    output.cpp.to_string(),
+                    output.outcome.name().to_string(),
+                    format!("{:.1}", output.seconds),
+                    output.failed_phase.unwrap_or("").to_string(),
+                    output.errors.len().to_string(),
+                    output.warnings.to_string(),
+                    output.passed_tests.to_string(),
+                    failed_tests.join(";"),
+                ];
+                let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                println!("{}", fields.join(","));
+            }
+        }
+        Format::Markdown => print_markdown(outputs),
+    }
+}
+
+// A table with a row per build type, CTK and compiler and a column per dialect,
+// followed by collapsed details of every cell that did not pass.
+fn print_markdown(outputs: &[CellOutput]) {
+    let mut cpps: Vec<&str> = Vec::new();
+    let mut rows: Vec<(&str, &str, &str)> = Vec::new();
+    for output in outputs {
+        if !cpps.contains(&output.cpp) {
+            cpps.push(output.cpp);
+        }
+        let row = (output.build_type, output.ctk, output.compiler);
+        if !rows.contains(&row) {
+            rows.push(row);
+        }
+    }
+
+    print!("| type | CTK | compiler |");
+    for cpp in &cpps {
+        print!(" C++{} |", cpp);
+    }
+    println!();
+    println!("|---|---|---|{}", "---|".repeat(cpps.len()));
+    for (build_type, ctk, compiler) in &rows {
+        print!("| {} | {} | `{}` |", build_type, ctk, compiler);
+        for cpp in &cpps {
+            let outcome = outputs
+                .iter()
+                .find(|o| {
+                    o.build_type == *build_type
+                        && o.ctk == *ctk
+                        && o.compiler == *compiler
+                        && o.cpp == *cpp
+                })
+                .map_or("", |o| markdown_outcome(o.outcome));
+            print!(" {} |", outcome);
+        }
+        println!();
+    }
+
+    for output in outputs {
+        if output.errors.is_empty() && output.timeout.is_none() && output.failed_tests.is_empty() {
+            continue;
+        }
+        println!();
+        println!(
+            "<details><summary>{} {}/{}/{}/cpp.{}</summary>",
+            markdown_outcome(output.outcome),
+            output.build_type,
+            output.ctk,
+            output.compiler,
+            output.cpp
+        );
+        println!();
+        println!("```");
+        if let (Some(phase), Some(timeout)) = (output.failed_phase, &output.timeout) {
+            println!("{} {}", phase, timeout);
+        }
+        for error in output.errors.iter().take(5) {
+            println!("{}", error);
+        }
+        if output.errors.len() > 5 {
+            println!("... and {} more errors", output.errors.len() - 5);
+        }
+        for test in &output.failed_tests {
+            println!("test {} {} ({:.2}s)", test.name, test.detail, test.seconds);
+        }
+        println!("```");
+        println!();
+        println!("</details>");
+    }
+}
@@ -0,0 +1,298 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+const CACHE_FILE: &str = "toolchains.json";
+
+/// Version banner of the tool at `path`, e.g. `g++ (GCC) 9.4.0` or
+/// `Cuda compilation tools, release 11.6, V11.6.124`.
+pub fn version(path: &Path) -> Option<String> {
+    let output = Command::new(path).arg("--version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+
+    // nvcc prints a copyright notice first, the release is on a later line
+    if let Some(release) = lines.clone().find(|l| l.contains(", release ")) {
+        return Some(release.to_string());
+    }
+    return lines.next().map(str::to_string);
+}
+
+pub fn nvcc(ctk_path: &str) -> PathBuf {
+    return Path::new(ctk_path).join("bin").join("nvcc");
+}
+
+/// Exact version in a banner, e.g. `9.4.0` for gcc, `11.6.124` for nvcc or
+/// `22.7-0` for nvc++.
+pub fn parse_version(banner: &str) -> Option<String> {
+    // nvcc's release line names the minor version before the exact one
+    let nvcc = Regex::new(r"\bV(\d+\.\d+\.\d+)").unwrap();
+    if let Some(caps) = nvcc.captures(banner) {
+        return Some(caps[1].to_string());
+    }
+    // gcc puts its version right after the parenthesized vendor, which may
+    // hold the package version, and clang after `version`. Distributions can
+    // append their package version to either, e.g. `(Red Hat 8.5.0-4)`.
+    let named = Regex::new(r"(?:\)|\bversion) (\d+\.\d+(?:\.\d+)*)\b").unwrap();
+    if let Some(caps) = named.captures(banner) {
+        return Some(caps[1].to_string());
+    }
+    let any = Regex::new(r"\b\d+\.\d+(\.\d+)*(-\d+)?\b").unwrap();
+    return any.find_iter(banner).last().map(|m| m.as_str().to_string());
+}
+
+/// Whether `version` is the one named by the last component of `label`, e.g.
+/// `8.5.0` for `gcc/8` or `11.6.124` for `11.6`. Labels not ending in a
+/// version, like `gcc/trunk`, match anything.
+pub fn matches_label(label: &str, version: &str) -> bool {
+    let expected = label.rsplit('/').next().unwrap_or(label);
+    if !expected.starts_with(|c: char| c.is_ascii_digit()) {
+        return true;
+    }
+    return version == expected
+        || version.starts_with(&format!("{}.", expected))
+        || version.starts_with(&format!("{}-", expected));
+}
+
+/// What `--version` reported for a toolchain binary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Probe {
+    pub path: String,
+    /// Modification time of the binary in nanoseconds since the Unix epoch.
+    pub modified: u64,
+    pub banner: String,
+    pub version: Option<String>,
+}
+
+impl Probe {
+    /// The parsed version, or the whole banner if it names none.
+    pub fn exact(&self) -> &str {
+        return self.version.as_deref().unwrap_or(&self.banner);
+    }
+}
+
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    return Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64);
+}
+
+/// Probes by binary path, kept in the state directory so that every
+/// toolchain is only run once until it is reinstalled.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Cache {
+    probes: BTreeMap<String, Probe>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl Cache {
+    pub fn load(state_dir: &Path) -> Self {
+        return fs::read_to_string(state_dir.join(CACHE_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+    }
+
+    pub fn probe(&mut self, path: &Path) -> Option<Probe> {
+        let key = path.display().to_string();
+        let modified = modified(path)?;
+        if let Some(probe) = self.probes.get(&key) {
+            // Only running the binary is expensive, the banner is parsed anew
+            if probe.modified == modified {
+                return Some(Probe {
+                    version: parse_version(&probe.banner),
+                    ..probe.clone()
+                });
+            }
+        }
+
+        let banner = version(path)?;
+        let probe = Probe {
+            path: key.clone(),
+            modified,
+            version: parse_version(&banner),
+            banner,
+        };
+        self.probes.insert(key, probe.clone());
+        self.changed = true;
+        return Some(probe);
+    }
+
+    pub fn save(&self, state_dir: &Path) {
+        if !self.changed {
+            return;
+        }
+        fs::create_dir_all(state_dir).ok();
+        if let Ok(json) = serde_json::to_string_pretty(self) {
+            fs::write(state_dir.join(CACHE_FILE), json).ok();
+        }
+    }
+}
+
+// Major version reported by `-dumpversion`, e.g. `12` for gcc 12.2.0.
+fn major_version(path: &Path) -> Option<String> {
+    let output = Command::new(path).arg("-dumpversion").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let major = text.trim().split('.').next()?;
+    if major.is_empty() || !major.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    return Some(major.to_string());
+}
+
+/// Host compilers found on `PATH` and in the default NVHPC install location,
+/// by label, e.g. `gcc/12` or `nvhpc/22.7`.
+pub fn discover_compilers() -> BTreeMap<String, String> {
+    let mut compilers: BTreeMap<String, String> = BTreeMap::new();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let name = Regex::new(r"^(g|clang)\+\+(-\d+)?$").unwrap();
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    for dir in env::split_paths(&path) {
+        let mut entries: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|e| Some(e.ok()?.path())).collect(),
+            Err(_) => continue,
+        };
+        entries.sort();
+        for entry in entries {
+            let caps = match entry
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| name.captures(n))
+            {
+                Some(caps) => caps,
+                None => continue,
+            };
+            // g++ and g++-12 are usually the same binary
+            if !seen.insert(fs::canonicalize(&entry).unwrap_or(entry.clone())) {
+                continue;
+            }
+            let family = if &caps[1] == "g" { "gcc" } else { "clang" };
+            if let Some(major) = major_version(&entry) {
+                compilers
+                    .entry(format!("{}/{}", family, major))
+                    .or_insert(entry.display().to_string());
+            }
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir("/opt/nvidia/hpc_sdk/Linux_x86_64") {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let nvcxx = entry.path().join("compilers").join("bin").join("nvc++");
+            if nvcxx.exists() {
+                compilers.insert(
+                    format!("nvhpc/{}", entry.file_name().to_string_lossy()),
+                    nvcxx.display().to_string(),
+                );
+            }
+        }
+    }
+
+    return compilers;
+}
+
+/// CUDA toolkits in `/usr/local/cuda-*`, `/usr/local/cuda` and `CUDA_HOME`,
+/// by their `major.minor` version.
+/// Label of the CUDA toolkit at `path` from its nvcc release, e.g. `11.6`.
+pub fn ctk_label(path: &str) -> Option<String> {
+    let release = Regex::new(r"release (\d+\.\d+)").unwrap();
+    let banner = version(&nvcc(path))?;
+    return Some(release.captures(&banner)?[1].to_string());
+}
+
+pub fn discover_ctks() -> BTreeMap<String, String> {
+    let mut ctks: BTreeMap<String, String> = BTreeMap::new();
+
+    let mut candidates: Vec<PathBuf> = match fs::read_dir("/usr/local") {
+        Ok(entries) => entries
+            .filter_map(|e| Some(e.ok()?.path()))
+            .filter(|p| p.to_string_lossy().starts_with("/usr/local/cuda-"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    candidates.sort();
+    candidates.push(PathBuf::from("/usr/local/cuda"));
+    if let Some(home) = env::var_os("CUDA_HOME") {
+        candidates.push(PathBuf::from(home));
+    }
+
+    for candidate in candidates {
+        let path = candidate.display().to_string();
+        if let Some(label) = ctk_label(&path) {
+            ctks.entry(label).or_insert(path);
+        }
+    }
+
+    return ctks;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(banner: &str) -> String {
+        return parse_version(banner).expect("a version");
+    }
+
+    #[test]
+    fn gcc_with_release_date() {
+        assert_eq!(parse("g++ (GCC) 8.5.0 20210514 (Red Hat 8.5.0-4)"), "8.5.0");
+        assert_eq!(parse("g++ (GCC) 9.4.0"), "9.4.0");
+    }
+
+    #[test]
+    fn gcc_with_distribution_version() {
+        assert_eq!(parse("g++ (Debian 12.2.0-14+deb12u1) 12.2.0"), "12.2.0");
+    }
+
+    #[test]
+    fn clang() {
+        assert_eq!(parse("Ubuntu clang version 14.0.0-1ubuntu1.1"), "14.0.0");
+    }
+
+    #[test]
+    fn nvcc() {
+        assert_eq!(
+            parse("Cuda compilation tools, release 11.6, V11.6.124"),
+            "11.6.124"
+        );
+    }
+
+    #[test]
+    fn nvhpc() {
+        assert_eq!(
+            parse("nvc++ 22.7-0 64-bit target on x86-64 Linux -tp zen2"),
+            "22.7-0"
+        );
+    }
+
+    #[test]
+    fn no_version() {
+        assert_eq!(parse_version("usage: g++ [options] file..."), None);
+    }
+
+    #[test]
+    fn label_prefix() {
+        assert!(matches_label("gcc/8", "8.5.0"));
+        assert!(matches_label("11.6", "11.6.124"));
+        assert!(matches_label("nvhpc/22.7", "22.7-0"));
+        assert!(matches_label("gcc/12", "12.2.0"));
+    }
+
+    #[test]
+    fn label_mismatch() {
+        assert!(!matches_label("gcc/9", "12.2.0"));
+        assert!(!matches_label("11.6", "11.60"));
+        assert!(!matches_label("gcc/1", "12.2.0"));
+    }
+
+    #[test]
+    fn label_without_version() {
+        assert!(matches_label("gcc/trunk", "14.0.1"));
+    }
+}